@@ -3,7 +3,7 @@ import re
 import subprocess
 import time
 from collections import defaultdict
-from datetime import datetime, timedelta, date
+from datetime import datetime
 from pathlib import Path
 
 from tqdm import tqdm
@@ -13,11 +13,11 @@ def build_backend():
     return subprocess.call(["cargo", "build", "--release"])
 
 
-def start_backend(strategy: str):
+def start_backend(strategy: str, log_file):
     return subprocess.Popen(
         ["cargo", "run", "--release", strategy],
-        stdout=subprocess.PIPE,
-        stderr=subprocess.PIPE,
+        stdout=log_file,
+        stderr=subprocess.DEVNULL,
     )
 
 
@@ -53,32 +53,44 @@ def extract_turns(output):
     return int(match.group(1)) if match else -1
 
 
-def calculate_average_turn_time(log_lines):
-    timestamps = []
-    for line in log_lines.strip().split("\n"):
-        match = re.search(r"INFO ([\d:.]+) Turn:", line)
-        if match:
-            parsed_time = datetime.strptime(match.group(1), "%H:%M:%S.%f").time()
-            parsed_dtime = datetime.combine(date.today(), parsed_time)
-            timestamps.append(parsed_dtime)
+def read_backend_records(backend_log: Path, offset: int):
+    """JSON log records the backend wrote since `offset`, and the new offset."""
+    with open(backend_log, "r") as f:
+        f.seek(offset)
+        new_output = f.read()
+    records = []
+    for line in new_output.splitlines():
+        try:
+            records.append(json.loads(line))
+        except json.JSONDecodeError:
+            continue
+    return records, offset + len(new_output.encode())
+
 
-    if len(timestamps) < 2:
-        return timedelta()
+def extract_death_cause(records):
+    causes = [r["death_cause"] for r in records if r.get("msg") == "end"]
+    return causes[-1] if causes else "unknown"
 
-        # Compute differences between consecutive times
-    diffs = [timestamps[i + 1] - timestamps[i] for i in range(len(timestamps) - 1)]
 
-    # Calculate average difference
-    avg_turn_time = sum(diffs, timedelta()) / len(diffs) if diffs else timedelta()
-    return avg_turn_time.total_seconds()
+def calculate_average_turn_time(records):
+    """Average decision time of the backend in seconds."""
+    durations = [r["decision_ms"] for r in records if r.get("msg") == "move"]
+    if not durations:
+        return 0.0
+    return sum(durations) / len(durations) / 1000
 
 
 def main():
-    strategies = ["random", "simple"]
+    strategies = ["random", "simple", "hamilton", "cutoff", "duel", "mcts"]
     board_sizes = [(10, 10), (15, 15), (20, 20), (40, 40), (60, 60)]
     results = []
     aggregated_results = defaultdict(
-        lambda: {"total_turns": 0, "total_games": 0, "total_time": 0.0}
+        lambda: {
+            "total_turns": 0,
+            "total_games": 0,
+            "total_time": 0.0,
+            "death_causes": defaultdict(int),
+        }
     )
     log_dir = Path("logs")
     log_dir.mkdir(exist_ok=True)
@@ -86,7 +98,10 @@ def main():
     build_backend()
 
     for strategy in tqdm(strategies, desc="Strategies", leave=True):
-        backend_proc = start_backend(strategy)
+        backend_log = log_dir / f"backend_{strategy}.log"
+        backend_log_file = open(backend_log, "w")
+        backend_log_offset = 0
+        backend_proc = start_backend(strategy, backend_log_file)
         time.sleep(2)  # Allow backend to start
 
         try:
@@ -94,7 +109,11 @@ def main():
                 for i in tqdm(range(100), desc="Games", leave=False):
                     output = run_battlesnake(width, height)
                     turns = extract_turns(output)
-                    avg_turn_time = calculate_average_turn_time(output)
+                    records, backend_log_offset = read_backend_records(
+                        backend_log, backend_log_offset
+                    )
+                    avg_turn_time = calculate_average_turn_time(records)
+                    death_cause = extract_death_cause(records)
 
                     game_result = {
                         "strategy": strategy,
@@ -102,17 +121,20 @@ def main():
                         "game_number": i + 1,
                         "turns": turns,
                         "avg_turn_time": avg_turn_time,
+                        "death_cause": death_cause,
                     }
                     results.append(game_result)
                     key = (strategy, f"{width}x{height}")
                     aggregated_results[key]["total_turns"] += turns
                     aggregated_results[key]["total_games"] += 1
+                    aggregated_results[key]["death_causes"][death_cause] += 1
                     aggregated_results[key]["total_time"] += (
                         avg_turn_time if avg_turn_time else 0.0
                     )
 
         finally:
             stop_backend(backend_proc)
+            backend_log_file.close()
 
     timestamp = datetime.now().strftime("%Y%m%d_%H%M%S")
     log_filename = log_dir / f"log_results_{timestamp}.json"
@@ -128,6 +150,7 @@ def main():
         aggregated_output[f"{strategy}_{board_size}"] = {
             "average_turns": avg_turns,
             "average_turn_time": avg_time,
+            "death_causes": dict(data["death_causes"]),
         }
 
     agg_filename = log_dir / f"agg_results_{timestamp}.json"
@@ -0,0 +1,234 @@
+use crate::game::Coord;
+
+/// A cell whose removal disconnects the free space, with the sizes of the regions that are cut
+/// off behind it as seen from the start of the analysis.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chokepoint {
+    pub cell: Coord,
+    pub regions: Vec<usize>,
+}
+
+impl Chokepoint {
+    pub fn cut_off_size(&self) -> usize {
+        self.regions.iter().sum()
+    }
+}
+
+/// The chokepoints of a free region are its articulation points.
+#[derive(Debug, Clone, Default)]
+pub struct FreeSpaceAnalysis {
+    pub chokepoints: Vec<Chokepoint>,
+}
+
+struct Tarjan {
+    width: i32,
+    height: i32,
+    free: Vec<bool>,
+    disc: Vec<u32>,
+    low: Vec<u32>,
+    subtree_size: Vec<usize>,
+    time: u32,
+    is_articulation: Vec<bool>,
+    regions_behind: Vec<Vec<usize>>,
+}
+
+impl Tarjan {
+    fn new(board_size: (i32, i32), blocked_pos: &[Coord]) -> Tarjan {
+        let (width, height) = board_size;
+        let n = (width * height) as usize;
+        let mut free = vec![true; n];
+        blocked_pos
+            .iter()
+            .filter(|c| c.x >= 0 && c.y >= 0 && c.x < width && c.y < height)
+            .for_each(|c| free[(c.y * width + c.x) as usize] = false);
+
+        Tarjan {
+            width,
+            height,
+            free,
+            disc: vec![0; n],
+            low: vec![0; n],
+            subtree_size: vec![0; n],
+            time: 0,
+            is_articulation: vec![false; n],
+            regions_behind: vec![vec![]; n],
+        }
+    }
+
+    fn idx(&self, c: &Coord) -> Option<usize> {
+        if c.x >= 0 && c.y >= 0 && c.x < self.width && c.y < self.height {
+            Some((c.y * self.width + c.x) as usize)
+        } else {
+            None
+        }
+    }
+
+    fn coord(&self, i: usize) -> Coord {
+        Coord {
+            x: i as i32 % self.width,
+            y: i as i32 / self.width,
+        }
+    }
+
+    fn neighbor(&self, v: usize, n: usize) -> Option<usize> {
+        let c = self.coord(v);
+        let next = match n {
+            0 => Coord { x: c.x - 1, y: c.y },
+            1 => Coord { x: c.x + 1, y: c.y },
+            2 => Coord { x: c.x, y: c.y + 1 },
+            _ => Coord { x: c.x, y: c.y - 1 },
+        };
+        self.idx(&next).filter(|i| self.free[*i])
+    }
+
+    // Iterative DFS, recursion would overflow the stack on large boards
+    fn run(&mut self, root: usize) {
+        self.time += 1;
+        self.disc[root] = self.time;
+        self.low[root] = self.time;
+        self.subtree_size[root] = 1;
+
+        let mut root_children = 0;
+        let mut stack = vec![(root, usize::MAX, 0)];
+
+        while let Some((v, parent, next)) = stack.last_mut() {
+            let (v, parent) = (*v, *parent);
+            if *next < 4 {
+                let n = *next;
+                *next += 1;
+                let Some(u) = self.neighbor(v, n) else {
+                    continue;
+                };
+                if self.disc[u] == 0 {
+                    if v == root {
+                        root_children += 1;
+                    }
+                    self.time += 1;
+                    self.disc[u] = self.time;
+                    self.low[u] = self.time;
+                    self.subtree_size[u] = 1;
+                    stack.push((u, v, 0));
+                } else if u != parent && self.disc[u] < self.disc[v] {
+                    self.low[v] = self.low[v].min(self.disc[u]);
+                }
+            } else {
+                stack.pop();
+                if let Some((p, _, _)) = stack.last() {
+                    let p = *p;
+                    self.low[p] = self.low[p].min(self.low[v]);
+                    self.subtree_size[p] += self.subtree_size[v];
+                    if self.low[v] >= self.disc[p] {
+                        // Everything below v can only be reached through p
+                        self.regions_behind[p].push(self.subtree_size[v]);
+                        if p != root {
+                            self.is_articulation[p] = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        if root_children >= 2 {
+            self.is_articulation[root] = true;
+        } else {
+            self.regions_behind[root].clear();
+        }
+    }
+
+    fn into_analysis(self) -> FreeSpaceAnalysis {
+        let chokepoints = (0..self.free.len())
+            .filter(|i| self.is_articulation[*i])
+            .map(|i| Chokepoint {
+                cell: self.coord(i),
+                regions: self.regions_behind[i].clone(),
+            })
+            .collect();
+        FreeSpaceAnalysis { chokepoints }
+    }
+}
+
+/// Analyzes the free region reachable from `start`. The regions of each chokepoint are the
+/// parts of the region that are only reachable from `start` by passing through it.
+pub fn analyze_region_from(
+    start: Coord,
+    board_size: (i32, i32),
+    blocked_pos: &[Coord],
+) -> FreeSpaceAnalysis {
+    let mut tarjan = Tarjan::new(board_size, blocked_pos);
+    match tarjan.idx(&start) {
+        Some(root) if tarjan.free[root] => {
+            tarjan.run(root);
+            tarjan.into_analysis()
+        }
+        _ => FreeSpaceAnalysis::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 5x3, two rooms connected by a one-cell corridor at (2,1)
+    // OOXOO
+    // OOOOO
+    // OOXOO
+    fn corridor() -> Vec<Coord> {
+        vec![Coord { x: 2, y: 0 }, Coord { x: 2, y: 2 }]
+    }
+
+    #[test]
+    fn test_open_board_has_no_chokepoints() {
+        let analysis = analyze_region_from(Coord { x: 1, y: 1 }, (4, 4), &[]);
+        assert!(analysis.chokepoints.is_empty());
+    }
+
+    #[test]
+    fn test_region_behind_corridor_seen_from_start() {
+        let analysis = analyze_region_from(Coord { x: 0, y: 0 }, (5, 3), &corridor());
+        let cut_off = analysis
+            .chokepoints
+            .iter()
+            .map(|c| (c.cell, c.regions.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cut_off,
+            vec![
+                (Coord { x: 1, y: 1 }, vec![7]),
+                (Coord { x: 2, y: 1 }, vec![6]),
+                (Coord { x: 3, y: 1 }, vec![5]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_line_of_cells() {
+        // 1x5 line, every inner cell is an articulation point
+        let analysis = analyze_region_from(Coord { x: 0, y: 0 }, (5, 1), &[]);
+        let mut cells = analysis
+            .chokepoints
+            .iter()
+            .map(|c| (c.cell.x, c.cut_off_size()))
+            .collect::<Vec<_>>();
+        cells.sort();
+        assert_eq!(cells, vec![(1, 3), (2, 2), (3, 1)]);
+    }
+
+    #[test]
+    fn test_start_on_corridor_splits_into_regions() {
+        // Starting on the corridor itself, both rooms are behind the start
+        let analysis = analyze_region_from(Coord { x: 2, y: 1 }, (5, 3), &corridor());
+        let corridor = analysis
+            .chokepoints
+            .iter()
+            .find(|c| c.cell == Coord { x: 2, y: 1 })
+            .unwrap();
+        assert_eq!(corridor.regions, vec![6, 6]);
+        assert_eq!(analysis.chokepoints.len(), 3);
+    }
+
+    #[test]
+    fn test_blocked_start() {
+        let analysis = analyze_region_from(Coord { x: 2, y: 0 }, (5, 3), &corridor());
+        assert!(analysis.chokepoints.is_empty());
+    }
+}
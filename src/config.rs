@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+
+/// Custom settings read from `Rocket.toml` or `ROCKET_*` environment variables.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Base seed for the per-move rng of the strategy
+    pub seed: u64,
+    /// Keep searching on the predicted next board after answering a move
+    pub ponder: bool,
+}
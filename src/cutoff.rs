@@ -0,0 +1,340 @@
+use crate::flood_fill::flood_fill;
+use crate::game::{all_directions, Battlesnake, Board, Coord, Direction};
+use crate::grid::Grid;
+use crate::pathfinding::dijkstra;
+use std::collections::VecDeque;
+
+/// Sequence of our moves that seals an opponent into a region smaller than its body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CutoffPlan {
+    pub target: String,
+    pub moves: Vec<Direction>,
+    /// Free cells left to the opponent once the plan is executed
+    pub region: usize,
+}
+
+struct Target<'a> {
+    snake: &'a Battlesnake,
+    /// Arrival time of the opponent on each cell
+    distances: Grid<u32>,
+}
+
+struct CutoffSearch<'a> {
+    board_size: (i32, i32),
+    snake: &'a Battlesnake,
+    /// Bodies of the other snakes
+    blocked_pos: Vec<Coord>,
+    /// Number of our moves after which each of our body cells is free
+    own_free_at: Grid<usize>,
+    targets: Vec<Target<'a>>,
+    path: Vec<Coord>,
+    moves: Vec<Direction>,
+    best: Option<CutoffPlan>,
+}
+
+// Number of moves after which each body cell is free, once the tail has moved past it
+fn free_at(body: &[Coord], board_size: (i32, i32)) -> Grid<usize> {
+    let mut free_at = Grid::new(board_size.0, board_size.1, 0);
+    for (i, c) in body.iter().enumerate() {
+        if let Some(f) = free_at.get_mut(c) {
+            *f = (*f).max(body.len() - i);
+        }
+    }
+    free_at
+}
+
+impl CutoffSearch<'_> {
+    // Whether our own body still covers `c` after `moves` moves
+    fn own_body_at(&self, c: &Coord, moves: usize) -> bool {
+        self.own_free_at.get(c).is_some_and(|f| *f > moves)
+    }
+
+    // Cells blocked once the path is taken, our tail has moved on by as many cells
+    fn blocked_with_path(&self, except: Coord) -> Vec<Coord> {
+        let own_body = self
+            .snake
+            .body
+            .iter()
+            .filter(|c| self.own_body_at(c, self.path.len()));
+        self.blocked_pos
+            .iter()
+            .chain(own_body)
+            .chain(&self.path)
+            .copied()
+            .filter(|c| *c != except)
+            .collect()
+    }
+
+    fn region_of(&self, start: Coord) -> usize {
+        let blocked = self.blocked_with_path(start);
+        flood_fill(start, &blocked, self.board_size.0, self.board_size.1)
+            .len()
+            .saturating_sub(1)
+    }
+
+    // Free cells the opponent can reach. Its own body cells count too once its tail has moved
+    // past them by the time it gets there, like the stall search does for our body.
+    fn region_of_target(&self, target: &Target) -> usize {
+        let (w, h) = self.board_size;
+        let body = &target.snake.body;
+        let free_at = free_at(body, self.board_size);
+        let walls = self
+            .blocked_with_path(target.snake.head)
+            .into_iter()
+            .filter(|c| !body.contains(c))
+            .collect::<Vec<_>>();
+        let mut visited = Grid::with_cells(w, h, false, &walls, true);
+        visited[target.snake.head] = true;
+
+        let mut region = 0;
+        let mut queue = VecDeque::from([(target.snake.head, 0)]);
+        while let Some((c, dist)) = queue.pop_front() {
+            for n in c.neighbors(self.board_size) {
+                if !visited[n] && free_at[n] <= dist + 1 {
+                    visited[n] = true;
+                    region += 1;
+                    queue.push_back((n, dist + 1));
+                }
+            }
+        }
+        region
+    }
+
+    fn evaluate(&mut self) {
+        let head = *self.path.last().unwrap();
+        // Sealing the opponent must not trap ourselves
+        if self.region_of(head) < self.snake.body.len() {
+            return;
+        }
+
+        for target in &self.targets {
+            let region = self.region_of_target(target);
+            if region >= target.snake.body.len() {
+                continue;
+            }
+            let better = self.best.as_ref().is_none_or(|b| region < b.region);
+            if better {
+                self.best = Some(CutoffPlan {
+                    target: target.snake.id.clone(),
+                    moves: self.moves.clone(),
+                    region,
+                });
+            }
+        }
+    }
+
+    fn search(&mut self, pos: Coord, depth: usize) {
+        if self.moves.len() == depth {
+            self.evaluate();
+            return;
+        }
+        let step = self.moves.len() as u32 + 1;
+
+        for dir in all_directions() {
+            let next = pos.next_coord_in_dir(&dir);
+            let (w, h) = self.board_size;
+            if next.x < 0 || next.y < 0 || next.x >= w || next.y >= h {
+                continue;
+            }
+            if self.blocked_pos.contains(&next)
+                || self.own_body_at(&next, step as usize)
+                || self.path.contains(&next)
+            {
+                continue;
+            }
+            // A cell only seals if we get there before every opponent
+            let contested = self
+                .targets
+                .iter()
+                .any(|t| *t.distances.get(&next).unwrap_or(&u32::MAX) <= step);
+            if contested {
+                continue;
+            }
+
+            self.path.push(next);
+            self.moves.push(dir);
+            self.search(next, depth);
+            self.moves.pop();
+            self.path.pop();
+        }
+    }
+}
+
+/// Searches our move sequences of up to `max_depth` moves for one that leaves an opponent with
+/// fewer free cells than its length, while we keep enough space for ourselves. Other bodies are
+/// treated as static, our body and the opponent's own body free up behind their tails. The
+/// opponents are assumed to contest every cell they can reach in time. Shorter plans are
+/// preferred, then the smallest remaining region.
+pub fn find_cutoff(board: &Board, snake: &Battlesnake, max_depth: usize) -> Option<CutoffPlan> {
+    let board_size = (board.width, board.height);
+    let blocked_pos = board
+        .snakes
+        .iter()
+        .flat_map(|s| s.body.clone())
+        .collect::<Vec<Coord>>();
+    let others = board
+        .snakes
+        .iter()
+        .filter(|s| s.id != snake.id)
+        .flat_map(|s| s.body.clone())
+        .collect::<Vec<Coord>>();
+    let targets = board
+        .snakes
+        .iter()
+        .filter(|s| s.id != snake.id)
+        .map(|s| Target {
+            snake: s,
+            distances: dijkstra(s.head, board_size, &blocked_pos)
+                .get_distance_ref()
+                .clone(),
+        })
+        .collect::<Vec<_>>();
+    if targets.is_empty() {
+        return None;
+    }
+
+    let mut search = CutoffSearch {
+        board_size,
+        snake,
+        blocked_pos: others,
+        own_free_at: free_at(&snake.body, board_size),
+        targets,
+        path: vec![],
+        moves: vec![],
+        best: None,
+    };
+    for depth in 1..=max_depth {
+        search.search(snake.head, depth);
+        if search.best.is_some() {
+            break;
+        }
+    }
+    search.best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{board, snake};
+
+    // 7x4, our body is a wall at x = 2 with a gap at the top, the opponent's head is at (1,1)
+    // .......
+    // X.M....
+    // XXM....
+    // XXMMM..
+    fn walled_in(opponent_len: usize) -> (Battlesnake, Board) {
+        let me = snake(
+            "me",
+            vec![
+                Coord { x: 2, y: 2 },
+                Coord { x: 2, y: 1 },
+                Coord { x: 2, y: 0 },
+                Coord { x: 3, y: 0 },
+                Coord { x: 4, y: 0 },
+            ],
+        );
+        let body = [
+            Coord { x: 1, y: 1 },
+            Coord { x: 1, y: 0 },
+            Coord { x: 0, y: 0 },
+            Coord { x: 0, y: 1 },
+            Coord { x: 0, y: 2 },
+        ];
+        let other = snake("other", body[..opponent_len].to_vec());
+        (me.clone(), board(7, 4, vec![me, other]))
+    }
+
+    #[test]
+    fn test_seals_dead_end_in_one_move() {
+        // 7x5, the opponent heads down column 0, we close its bottom end
+        // OMM....
+        // OM.....
+        // OM.....
+        // .M.....
+        // .......
+        let me = snake(
+            "me",
+            vec![
+                Coord { x: 1, y: 1 },
+                Coord { x: 1, y: 2 },
+                Coord { x: 1, y: 3 },
+                Coord { x: 1, y: 4 },
+                Coord { x: 2, y: 4 },
+            ],
+        );
+        let other = snake(
+            "other",
+            vec![
+                Coord { x: 0, y: 2 },
+                Coord { x: 0, y: 3 },
+                Coord { x: 0, y: 4 },
+            ],
+        );
+        let b = board(7, 5, vec![me.clone(), other]);
+        let plan = find_cutoff(&b, &me, 3).unwrap();
+
+        assert_eq!(plan.target, "other");
+        assert_eq!(plan.moves, vec![Direction::Down]);
+        // Its body only frees up behind it, out of reach
+        assert_eq!(plan.region, 2);
+    }
+
+    #[test]
+    fn test_opponent_following_its_tail_is_not_trapped() {
+        // The pocket only has three free cells, but the opponent can circle behind its tail
+        let (me, b) = walled_in(5);
+        assert_eq!(find_cutoff(&b, &me, 1), None);
+    }
+
+    #[test]
+    fn test_no_cutoff_if_region_fits_the_opponent() {
+        // The six cells left behind the wall are enough for a short snake
+        let (me, b) = walled_in(2);
+        assert_eq!(find_cutoff(&b, &me, 1), None);
+    }
+
+    #[test]
+    fn test_own_vacating_tail_does_not_seal() {
+        // 6x5, the only way out of column 0 leads across our tail at (1,0), which moves away
+        // .M....
+        // OM....
+        // OM....
+        // OM....
+        // .M....
+        let me = snake(
+            "me",
+            vec![
+                Coord { x: 1, y: 4 },
+                Coord { x: 1, y: 3 },
+                Coord { x: 1, y: 2 },
+                Coord { x: 1, y: 1 },
+                Coord { x: 1, y: 0 },
+            ],
+        );
+        let other = snake(
+            "other",
+            vec![
+                Coord { x: 0, y: 1 },
+                Coord { x: 0, y: 2 },
+                Coord { x: 0, y: 3 },
+            ],
+        );
+        let b = board(6, 5, vec![me.clone(), other]);
+        assert_eq!(find_cutoff(&b, &me, 3), None);
+    }
+
+    #[test]
+    fn test_no_cutoff_on_open_board() {
+        let me = snake("me", vec![Coord { x: 0, y: 0 }, Coord { x: 0, y: 1 }]);
+        let other = snake("other", vec![Coord { x: 6, y: 6 }, Coord { x: 6, y: 5 }]);
+        let b = board(7, 7, vec![me.clone(), other]);
+        assert_eq!(find_cutoff(&b, &me, 3), None);
+    }
+
+    #[test]
+    fn test_no_cutoff_without_opponents() {
+        let me = snake("me", vec![Coord { x: 0, y: 0 }, Coord { x: 0, y: 1 }]);
+        let b = board(7, 7, vec![me.clone()]);
+        assert_eq!(find_cutoff(&b, &me, 3), None);
+    }
+}
@@ -0,0 +1,386 @@
+use crate::flood_fill::flood_fill;
+use crate::game::{Battlesnake, Board, Coord, Direction};
+use crate::move_filter::MoveFilters;
+use crate::parallel::{search_roots, Deadline};
+use crate::rules::{step, Ruleset};
+use crate::transposition::TranspositionTable;
+use crate::zobrist::ZobristKeys;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// Free cells both snakes may still reach for the board to count as nearly partitioned
+const NEARLY_PARTITIONED_CELLS: usize = 3;
+const TABLE_SIZE: usize = 1 << 16;
+// Positions expanded between two looks at the clock
+const DEADLINE_CHECK_INTERVAL: usize = 256;
+
+/// Result of the exact search from our point of view, ordered from worst to best.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Outcome {
+    Loss,
+    /// Both snakes are eliminated in the same turn
+    Draw,
+    /// Not decided within the search depth
+    Unknown,
+    Win,
+}
+
+impl Outcome {
+    pub fn is_proven(self) -> bool {
+        self != Outcome::Unknown
+    }
+}
+
+/// Outcome of each of our moves, from the deepest fully searched iteration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuelResult {
+    pub moves: Vec<(Direction, Outcome)>,
+    pub depth: usize,
+}
+
+impl DuelResult {
+    pub fn best(&self) -> Option<Outcome> {
+        self.moves.iter().map(|(_, o)| *o).max()
+    }
+
+    pub fn outcome_of(&self, dir: Direction) -> Option<Outcome> {
+        self.moves.iter().find(|(d, _)| *d == dir).map(|(_, o)| *o)
+    }
+}
+
+/// Whether exactly two snakes are left and they share at most a few free cells, so that an
+/// exact search of the remaining game is feasible.
+pub fn is_duel_endgame(board: &Board) -> bool {
+    if board.snakes.len() != 2 {
+        return false;
+    }
+    let blocked_pos = board
+        .snakes
+        .iter()
+        .flat_map(|s| s.body.clone())
+        .collect::<Vec<Coord>>();
+    let regions = board
+        .snakes
+        .iter()
+        .map(|s| {
+            let blocked = blocked_pos
+                .iter()
+                .copied()
+                .filter(|c| *c != s.head)
+                .collect::<Vec<_>>();
+            let mut region = flood_fill(s.head, &blocked, board.width, board.height);
+            region.retain(|c| *c != s.head);
+            region
+        })
+        .collect::<Vec<_>>();
+
+    let shared = regions[0].iter().filter(|c| regions[1].contains(c)).count();
+    shared <= NEARLY_PARTITIONED_CELLS
+}
+
+// Moves that pass the legal move filters. Without any such move the snake is dead anyway,
+// a single move is enough to search.
+pub fn candidate_moves(board: &Board, snake: &Battlesnake) -> Vec<Direction> {
+    static LEGAL: OnceLock<MoveFilters> = OnceLock::new();
+    let moves = LEGAL.get_or_init(MoveFilters::legal).allowed(board, snake);
+    if moves.is_empty() {
+        vec![Direction::Up]
+    } else {
+        moves
+    }
+}
+
+// The outcome of one of our moves given the outcomes of the opponent's replies. A draw is only
+// proven if no reply is undecided, an undecided one may still turn out as a loss.
+fn worst_reply(outcomes: &[Outcome]) -> Outcome {
+    let worst = outcomes.iter().copied().min().unwrap_or(Outcome::Win);
+    if worst == Outcome::Draw && outcomes.contains(&Outcome::Unknown) {
+        Outcome::Unknown
+    } else {
+        worst
+    }
+}
+
+struct DuelSolver {
+    me: String,
+    opponent: String,
+    // No food spawns during the search, the outcome is only exact for the known food
+    ruleset: Ruleset,
+    rng: StdRng,
+    // Health is hashed exactly, starvation decides many endgames
+    keys: ZobristKeys,
+    table: TranspositionTable<Outcome>,
+    nodes: usize,
+    max_nodes: usize,
+    deadline: Deadline,
+    aborted: bool,
+}
+
+impl DuelSolver {
+    fn new(
+        board: &Board,
+        me: &str,
+        opponent: &str,
+        max_nodes: usize,
+        deadline: Deadline,
+    ) -> DuelSolver {
+        DuelSolver {
+            me: me.to_string(),
+            opponent: opponent.to_string(),
+            ruleset: Ruleset::no_spawn(),
+            rng: StdRng::seed_from_u64(0),
+            keys: ZobristKeys::new(board.width, board.height, 1),
+            table: TranspositionTable::new(TABLE_SIZE),
+            nodes: 0,
+            max_nodes,
+            deadline,
+            aborted: false,
+        }
+    }
+
+    // Outcome of `my_move` against the reply of the opponent that is worst for us
+    fn move_outcome(
+        &mut self,
+        board: &Board,
+        my_move: Direction,
+        opponent_moves: &[Direction],
+        depth: usize,
+    ) -> Outcome {
+        let mut outcomes = vec![];
+        for opponent_move in opponent_moves {
+            let outcome = self.reply_outcome(board, my_move, *opponent_move, depth);
+            outcomes.push(outcome);
+            if outcome == Outcome::Loss {
+                break;
+            }
+        }
+        worst_reply(&outcomes)
+    }
+
+    fn reply_outcome(
+        &mut self,
+        board: &Board,
+        my_move: Direction,
+        opponent_move: Direction,
+        depth: usize,
+    ) -> Outcome {
+        let mut child = board.clone();
+        let moves = HashMap::from([
+            (self.me.clone(), my_move),
+            (self.opponent.clone(), opponent_move),
+        ]);
+        let eliminated = step(&mut child, &moves, &self.ruleset, &mut self.rng);
+        let me_dead = eliminated.iter().any(|(id, _)| *id == self.me);
+        let opponent_dead = eliminated.iter().any(|(id, _)| *id == self.opponent);
+
+        match (me_dead, opponent_dead) {
+            (true, true) => Outcome::Draw,
+            (true, false) => Outcome::Loss,
+            (false, true) => Outcome::Win,
+            (false, false) => self.value(&child, depth - 1),
+        }
+    }
+
+    fn value(&mut self, board: &Board, depth: usize) -> Outcome {
+        if depth == 0 || self.aborted {
+            return Outcome::Unknown;
+        }
+        let key = self.keys.hash(board);
+        if let Some((searched, outcome)) = self.table.probe(key) {
+            if outcome.is_proven() || searched >= depth {
+                return outcome;
+            }
+        }
+
+        self.nodes += 1;
+        if self.nodes > self.max_nodes
+            || (self.nodes.is_multiple_of(DEADLINE_CHECK_INTERVAL) && self.deadline.expired())
+        {
+            self.aborted = true;
+            return Outcome::Unknown;
+        }
+
+        let (Some(me), Some(opponent)) = (
+            board.snakes.iter().find(|s| s.id == self.me),
+            board.snakes.iter().find(|s| s.id == self.opponent),
+        ) else {
+            return Outcome::Unknown;
+        };
+        let opponent_moves = candidate_moves(board, opponent);
+
+        let mut best = Outcome::Loss;
+        for my_move in candidate_moves(board, me) {
+            best = best.max(self.move_outcome(board, my_move, &opponent_moves, depth));
+            if best == Outcome::Win {
+                break;
+            }
+        }
+        if !self.aborted {
+            self.table.store(key, depth, best);
+        }
+        best
+    }
+}
+
+/// Exact simultaneous-move search of a game with two snakes. Every pair of our move and an
+/// opponent reply is deepened iteratively as its own task on the search pool, up to
+/// `max_depth` turns, until its outcome is proven, `max_nodes` positions are expanded in total
+/// or the deadline expires. Returns `None` if not even a single turn could be searched.
+pub fn solve_duel(
+    board: &Board,
+    snake: &Battlesnake,
+    max_depth: usize,
+    max_nodes: usize,
+    deadline: &Deadline,
+) -> Option<DuelResult> {
+    let opponent = board.snakes.iter().find(|s| s.id != snake.id)?;
+    let opponent_moves = candidate_moves(board, opponent);
+    let root_moves = candidate_moves(board, snake);
+    let pairs = root_moves
+        .iter()
+        .flat_map(|m| opponent_moves.iter().map(move |o| (*m, *o)))
+        .collect::<Vec<_>>();
+    let nodes_per_pair = max_nodes / pairs.len();
+
+    let searched = search_roots(&pairs, deadline, |(my_move, opponent_move), deadline| {
+        let mut solver = DuelSolver::new(
+            board,
+            &snake.id,
+            &opponent.id,
+            nodes_per_pair,
+            deadline.clone(),
+        );
+        let mut result = None;
+        for depth in 1..=max_depth {
+            // Results of shallower iterations are replaced first
+            solver.table.new_search();
+            let outcome = solver.reply_outcome(board, *my_move, *opponent_move, depth);
+            if solver.aborted {
+                break;
+            }
+            result = Some((depth, outcome));
+            if outcome.is_proven() {
+                break;
+            }
+        }
+        deadline.add_nodes(solver.nodes as u64);
+        result
+    });
+
+    let mut moves = vec![];
+    let mut depth = max_depth;
+    for (my_move, replies) in root_moves.iter().zip(searched.chunks(opponent_moves.len())) {
+        let mut outcomes = vec![];
+        for reply in replies {
+            let (d, outcome) = (*reply)?;
+            outcomes.push(outcome);
+            depth = depth.min(d);
+        }
+        moves.push((*my_move, worst_reply(&outcomes)));
+    }
+    Some(DuelResult { moves, depth })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{board, board_with_food, snake, snake_with_health};
+
+    fn opponent(health: i32) -> Battlesnake {
+        snake_with_health(
+            "other",
+            vec![
+                Coord { x: 0, y: 0 },
+                Coord { x: 0, y: 1 },
+                Coord { x: 0, y: 2 },
+            ],
+            health,
+        )
+    }
+
+    #[test]
+    fn test_partitioned_board_is_duel_endgame() {
+        // Our body is a wall along x = 3, the head looks to the right
+        let mut body = vec![Coord { x: 4, y: 1 }, Coord { x: 4, y: 0 }];
+        body.extend((0..7).map(|y| Coord { x: 3, y }));
+        let me = snake("me", body);
+        assert!(is_duel_endgame(&board(7, 7, vec![me, opponent(100)])));
+
+        let me = snake("me", vec![Coord { x: 4, y: 1 }, Coord { x: 4, y: 0 }]);
+        assert!(!is_duel_endgame(&board(
+            7,
+            7,
+            vec![me.clone(), opponent(100)]
+        )));
+        assert!(!is_duel_endgame(&board(7, 7, vec![me])));
+    }
+
+    #[test]
+    fn test_starving_opponent_is_a_win() {
+        let me = snake(
+            "me",
+            vec![
+                Coord { x: 4, y: 4 },
+                Coord { x: 4, y: 3 },
+                Coord { x: 4, y: 2 },
+            ],
+        );
+        let b = board(7, 7, vec![me.clone(), opponent(1)]);
+
+        let result = solve_duel(&b, &me, 5, 10_000, &Deadline::never()).unwrap();
+        assert_eq!(result.depth, 1);
+        assert_eq!(result.best(), Some(Outcome::Win));
+        // Running into our own neck still loses
+        assert!(result.outcome_of(Direction::Down).is_none());
+    }
+
+    #[test]
+    fn test_finds_the_only_surviving_line() {
+        // Only the food two moves to the left saves us from starving
+        let me = snake_with_health("me", vec![Coord { x: 4, y: 4 }, Coord { x: 5, y: 4 }], 2);
+        let b = board_with_food(
+            7,
+            7,
+            vec![me.clone(), opponent(100)],
+            vec![Coord { x: 2, y: 4 }],
+        );
+
+        let result = solve_duel(&b, &me, 3, 100_000, &Deadline::never()).unwrap();
+        assert_eq!(result.outcome_of(Direction::Left), Some(Outcome::Unknown));
+        assert_eq!(result.outcome_of(Direction::Up), Some(Outcome::Loss));
+        assert_eq!(result.outcome_of(Direction::Down), Some(Outcome::Loss));
+    }
+
+    #[test]
+    fn test_both_starving_is_a_draw() {
+        let me = snake_with_health("me", vec![Coord { x: 4, y: 4 }, Coord { x: 4, y: 3 }], 1);
+        let b = board(7, 7, vec![me.clone(), opponent(1)]);
+
+        let result = solve_duel(&b, &me, 3, 10_000, &Deadline::never()).unwrap();
+        assert_eq!(result.best(), Some(Outcome::Draw));
+    }
+
+    #[test]
+    fn test_draw_is_not_proven_while_a_reply_is_undecided() {
+        // Moving left meets the opponent head-on, unless it turns away
+        let me = snake("me", vec![Coord { x: 4, y: 4 }, Coord { x: 5, y: 4 }]);
+        let other = snake("other", vec![Coord { x: 2, y: 4 }, Coord { x: 1, y: 4 }]);
+        let b = board(7, 7, vec![me.clone(), other]);
+
+        let result = solve_duel(&b, &me, 1, 10_000, &Deadline::never()).unwrap();
+        assert_eq!(result.outcome_of(Direction::Left), Some(Outcome::Unknown));
+    }
+
+    #[test]
+    fn test_node_budget() {
+        let me = snake("me", vec![Coord { x: 4, y: 4 }, Coord { x: 4, y: 3 }]);
+        let b = board(7, 7, vec![me.clone(), opponent(100)]);
+
+        // Nothing is decided on an open board, the search stops at the budget
+        let result = solve_duel(&b, &me, 50, 2_000, &Deadline::never()).unwrap();
+        assert!(result.depth < 50);
+        assert_eq!(result.best(), Some(Outcome::Unknown));
+    }
+}
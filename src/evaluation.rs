@@ -0,0 +1,209 @@
+use crate::flood_fill::flood_fill;
+use crate::game::{Battlesnake, Board, Coord, Direction};
+use crate::pathfinding::astar;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Weights of the evaluation terms used by the `WeightedStrategy`.
+/// All terms are normalized to roughly [-1, 1] so the weights are comparable.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EvalWeights {
+    pub space: f64,
+    pub trapped: f64,
+    pub food: f64,
+    pub hunger: f64,
+    pub center: f64,
+    pub head_danger: f64,
+}
+
+impl Default for EvalWeights {
+    fn default() -> Self {
+        EvalWeights {
+            space: 1.0,
+            trapped: 2.0,
+            food: 0.5,
+            hunger: 1.0,
+            center: 0.1,
+            head_danger: 1.0,
+        }
+    }
+}
+
+impl EvalWeights {
+    pub const LEN: usize = 6;
+
+    pub fn to_vec(&self) -> Vec<f64> {
+        vec![
+            self.space,
+            self.trapped,
+            self.food,
+            self.hunger,
+            self.center,
+            self.head_danger,
+        ]
+    }
+
+    pub fn from_vec(v: &[f64]) -> EvalWeights {
+        assert_eq!(v.len(), Self::LEN, "Invalid number of weights");
+        EvalWeights {
+            space: v[0],
+            trapped: v[1],
+            food: v[2],
+            hunger: v[3],
+            center: v[4],
+            head_danger: v[5],
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<EvalWeights, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read weights {}: {}", path.display(), e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| format!("Could not parse weights {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Raw, unweighted evaluation terms for moving `snake` one step in a direction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalTerms {
+    pub space: f64,
+    pub trapped: f64,
+    pub food: f64,
+    pub hunger: f64,
+    pub center: f64,
+    pub head_danger: f64,
+}
+
+impl EvalTerms {
+    pub fn score(&self, weights: &EvalWeights) -> f64 {
+        self.space * weights.space
+            + self.trapped * weights.trapped
+            + self.food * weights.food
+            + self.hunger * weights.hunger
+            + self.center * weights.center
+            + self.head_danger * weights.head_danger
+    }
+}
+
+/// Computes the evaluation terms for the position after moving in `dir`.
+/// Returns `None` if the move leaves the board or runs into a snake body.
+pub fn evaluate_move(board: &Board, snake: &Battlesnake, dir: &Direction) -> Option<EvalTerms> {
+    let next = snake.head.next_coord_in_dir(dir);
+    if next.x < 0 || next.y < 0 || next.x >= board.width || next.y >= board.height {
+        return None;
+    }
+
+    let blocked_pos = board
+        .snakes
+        .iter()
+        .flat_map(|s| s.body.clone())
+        .collect::<Vec<Coord>>();
+    if blocked_pos.contains(&next) {
+        return None;
+    }
+
+    let board_size = (board.width, board.height);
+    let max_dist = (board.width + board.height) as f64;
+
+    let reachable = flood_fill(next, &blocked_pos, board.width, board.height).len();
+    let space = reachable as f64 / (board.width * board.height) as f64;
+    let trapped = if reachable < snake.body.len() {
+        -1.0
+    } else {
+        0.0
+    };
+
+    let food_dist = astar(next, board_size, &blocked_pos, &board.food)
+        .retrieve_distances_for(&board.food)
+        .map(|(_, d)| *d)
+        .min()
+        .filter(|d| *d != u32::MAX);
+    let food_closeness = food_dist.map_or(0.0, |d| 1.0 - (d as f64 / max_dist).min(1.0));
+    let hunger = food_closeness * (100 - snake.health.clamp(0, 100)) as f64 / 100.0;
+
+    let center = Coord {
+        x: (board.width - 1) / 2,
+        y: (board.height - 1) / 2,
+    };
+    let center_dist = ((next.x - center.x).abs() + (next.y - center.y).abs()) as f64;
+
+    let head_danger = board
+        .snakes
+        .iter()
+        .filter(|s| s.id != snake.id && s.body.len() >= snake.body.len())
+        .filter(|s| (s.head.x - next.x).abs() + (s.head.y - next.y).abs() == 1)
+        .count();
+
+    Some(EvalTerms {
+        space,
+        trapped,
+        food: food_closeness,
+        hunger,
+        center: -center_dist / max_dist,
+        head_danger: -(head_danger as f64),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{snake, snake_with_health};
+
+    #[test]
+    fn test_weights_vec_roundtrip() {
+        let weights = EvalWeights::default();
+        assert_eq!(EvalWeights::from_vec(&weights.to_vec()), weights);
+        assert_eq!(weights.to_vec().len(), EvalWeights::LEN);
+    }
+
+    #[test]
+    fn test_illegal_moves_are_not_evaluated() {
+        let me = snake("me", vec![Coord { x: 0, y: 0 }, Coord { x: 1, y: 0 }]);
+        let board = Board {
+            height: 3,
+            width: 3,
+            food: vec![],
+            snakes: vec![me.clone()],
+            hazards: vec![],
+        };
+
+        assert!(evaluate_move(&board, &me, &Direction::Left).is_none());
+        assert!(evaluate_move(&board, &me, &Direction::Down).is_none());
+        assert!(evaluate_move(&board, &me, &Direction::Right).is_none());
+        assert!(evaluate_move(&board, &me, &Direction::Up).is_some());
+    }
+
+    #[test]
+    fn test_food_and_head_danger_terms() {
+        let me = snake_with_health("me", vec![Coord { x: 2, y: 2 }, Coord { x: 2, y: 1 }], 50);
+        let other = snake(
+            "other",
+            vec![
+                Coord { x: 4, y: 2 },
+                Coord { x: 4, y: 1 },
+                Coord { x: 4, y: 0 },
+            ],
+        );
+        let board = Board {
+            height: 5,
+            width: 5,
+            food: vec![Coord { x: 0, y: 2 }],
+            snakes: vec![me.clone(), other],
+            hazards: vec![],
+        };
+
+        let left = evaluate_move(&board, &me, &Direction::Left).unwrap();
+        let right = evaluate_move(&board, &me, &Direction::Right).unwrap();
+
+        assert!(left.food > right.food);
+        assert_eq!(left.hunger, left.food * 0.5);
+        assert_eq!(left.head_danger, 0.0);
+        assert_eq!(right.head_danger, -1.0);
+    }
+}
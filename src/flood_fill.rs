@@ -1,4 +1,5 @@
-use crate::game::{all_directions, Coord};
+use crate::game::Coord;
+use crate::grid::Grid;
 use std::collections::VecDeque;
 
 pub fn flood_fill(
@@ -7,32 +8,24 @@ pub fn flood_fill(
     board_width: i32,
     board_height: i32,
 ) -> Vec<Coord> {
-    if blocked_coords.iter().any(|c| *c == start) {
-        return vec![];
+    // Blocked cells are marked as visited up front
+    let mut visited = Grid::with_cells(board_width, board_height, false, blocked_coords, true);
+    match visited.get_mut(&start) {
+        Some(v) if !*v => *v = true,
+        _ => return vec![],
     }
-    let idx = |x: i32, y: i32| -> usize { (y * board_width + x) as usize };
 
-    let mut visited = vec![false; (board_width * board_height) as usize];
     let mut queue = VecDeque::new();
     let mut result = Vec::new();
 
     queue.push_back(start);
-    visited[idx(start.x, start.y)] = true;
 
     while let Some(current) = queue.pop_front() {
         result.push(current);
 
-        for direction in &all_directions() {
-            let next = current.next_coord_in_dir(direction);
-
-            if next.y >= 0
-                && next.x >= 0
-                && next.x < board_width
-                && next.y < board_height
-                && !blocked_coords.iter().any(|c| *c == next)
-                && !visited[idx(next.x, next.y)]
-            {
-                visited[idx(next.x, next.y)] = true;
+        for next in current.neighbors((board_width, board_height)) {
+            if !visited[next] {
+                visited[next] = true;
                 queue.push_back(next);
             }
         }
@@ -0,0 +1,169 @@
+use crate::game::{Battlesnake, Board, Coord};
+use crate::grid::Grid;
+use crate::pathfinding::dijkstra;
+
+/// Which snakes reach a food first, based on the shortest paths from every head.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoodContest {
+    pub food: Coord,
+    /// Snakes with the shortest distance to the food, more than one on a tie
+    pub closest: Vec<String>,
+    /// Distance of the closest snakes, `u32::MAX` if nobody can reach the food
+    pub distance: u32,
+    /// Number of moves the closest snakes are ahead of the next snake, 0 on a tie and
+    /// `u32::MAX` if no other snake can reach the food
+    pub margin: u32,
+}
+
+impl FoodContest {
+    /// Whether `snake` gets the food when heading straight for it: it is the only closest
+    /// snake, or all snakes arriving at the same time are shorter and lose the head-to-head.
+    pub fn is_won_by(&self, snake: &Battlesnake, board: &Board) -> bool {
+        if self.distance == u32::MAX || !self.closest.contains(&snake.id) {
+            return false;
+        }
+        board
+            .snakes
+            .iter()
+            .filter(|s| s.id != snake.id && self.closest.contains(&s.id))
+            .all(|s| s.body.len() < snake.body.len())
+    }
+}
+
+/// Computes the contest for every item in `board.food`, in the same order.
+pub fn contest_food(board: &Board) -> Vec<FoodContest> {
+    let board_size = (board.width, board.height);
+    let blocked_pos = board
+        .snakes
+        .iter()
+        .flat_map(|s| s.body.clone())
+        .collect::<Vec<Coord>>();
+    let distances = board
+        .snakes
+        .iter()
+        .map(|s| {
+            let result = dijkstra(s.head, board_size, &blocked_pos);
+            (s.id.clone(), result.get_distance_ref().clone())
+        })
+        .collect::<Vec<(String, Grid<u32>)>>();
+
+    board
+        .food
+        .iter()
+        .map(|food| {
+            let mut by_distance = distances
+                .iter()
+                .map(|(id, grid)| (id, *grid.get(food).unwrap_or(&u32::MAX)))
+                .filter(|(_, d)| *d != u32::MAX)
+                .collect::<Vec<_>>();
+            by_distance.sort_by_key(|(_, d)| *d);
+
+            let distance = by_distance.first().map_or(u32::MAX, |(_, d)| *d);
+            let closest = by_distance
+                .iter()
+                .take_while(|(_, d)| *d == distance)
+                .map(|(id, _)| id.to_string())
+                .collect::<Vec<_>>();
+            let margin = by_distance
+                .get(closest.len())
+                .map_or(u32::MAX, |(_, d)| d - distance);
+            let margin = if closest.len() > 1 { 0 } else { margin };
+
+            FoodContest {
+                food: *food,
+                closest,
+                distance,
+                margin,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{board_with_food, snake};
+
+    #[test]
+    fn test_closest_snake_and_margin() {
+        let me = snake("me", vec![Coord { x: 0, y: 0 }, Coord { x: 0, y: 1 }]);
+        let other = snake("other", vec![Coord { x: 6, y: 0 }, Coord { x: 6, y: 1 }]);
+        let b = board_with_food(7, 7, vec![me.clone(), other], vec![Coord { x: 1, y: 0 }]);
+
+        let contests = contest_food(&b);
+        assert_eq!(contests.len(), 1);
+        assert_eq!(contests[0].closest, vec!["me".to_string()]);
+        assert_eq!(contests[0].distance, 1);
+        assert_eq!(contests[0].margin, 4);
+        assert!(contests[0].is_won_by(&me, &b));
+    }
+
+    #[test]
+    fn test_nearest_food_lost_to_longer_opponent() {
+        // Our nearest food is two moves away, but a longer snake is next to it
+        let me = snake("me", vec![Coord { x: 3, y: 1 }, Coord { x: 3, y: 0 }]);
+        let other = snake(
+            "other",
+            vec![
+                Coord { x: 4, y: 3 },
+                Coord { x: 5, y: 3 },
+                Coord { x: 6, y: 3 },
+            ],
+        );
+        let near = Coord { x: 3, y: 3 };
+        let far = Coord { x: 0, y: 0 };
+        let b = board_with_food(7, 7, vec![me.clone(), other.clone()], vec![near, far]);
+
+        let contests = contest_food(&b);
+        assert_eq!(contests[0].closest, vec!["other".to_string()]);
+        assert_eq!(contests[0].margin, 1);
+        assert!(!contests[0].is_won_by(&me, &b));
+        assert!(contests[0].is_won_by(&other, &b));
+        assert_eq!(contests[1].food, far);
+        assert!(contests[1].is_won_by(&me, &b));
+    }
+
+    #[test]
+    fn test_tie_is_won_by_longer_snake() {
+        let me = snake(
+            "me",
+            vec![
+                Coord { x: 1, y: 3 },
+                Coord { x: 0, y: 3 },
+                Coord { x: 0, y: 2 },
+            ],
+        );
+        let other = snake("other", vec![Coord { x: 5, y: 3 }, Coord { x: 6, y: 3 }]);
+        let b = board_with_food(
+            7,
+            7,
+            vec![me.clone(), other.clone()],
+            vec![Coord { x: 3, y: 3 }],
+        );
+
+        let contest = &contest_food(&b)[0];
+        assert_eq!(contest.closest.len(), 2);
+        assert_eq!(contest.margin, 0);
+        assert!(contest.is_won_by(&me, &b));
+        assert!(!contest.is_won_by(&other, &b));
+    }
+
+    #[test]
+    fn test_food_behind_wall() {
+        let me = snake("me", vec![Coord { x: 0, y: 0 }, Coord { x: 0, y: 1 }]);
+        let wall = snake(
+            "wall",
+            (0..7).rev().map(|x| Coord { x, y: 4 }).collect::<Vec<_>>(),
+        );
+        let b = board_with_food(7, 7, vec![me.clone(), wall], vec![Coord { x: 6, y: 6 }]);
+
+        let contest = &contest_food(&b)[0];
+        assert_eq!(contest.closest, vec!["wall".to_string()]);
+        assert_eq!(contest.distance, 2);
+        assert_eq!(contest.margin, u32::MAX);
+        assert!(!contest.is_won_by(&me, &b));
+
+        let nobody = board_with_food(7, 7, vec![], vec![Coord { x: 6, y: 6 }]);
+        assert_eq!(contest_food(&nobody)[0].distance, u32::MAX);
+    }
+}
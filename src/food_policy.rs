@@ -0,0 +1,176 @@
+use crate::food_contest::{contest_food, FoodContest};
+use crate::game::{Battlesnake, Board, Coord};
+use crate::utils::parse_num;
+
+// Health kept in reserve on top of the distance to the nearest food, so that a detour
+// around a body does not starve us
+const HEALTH_BUFFER: u32 = 5;
+
+/// Decides which food a strategy should head for, if any.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum FoodPolicy {
+    /// Always chase the nearest food we win
+    #[default]
+    Always,
+    /// Only eat when health is below the threshold
+    Hungry { threshold: i32 },
+    /// Eat until we are longer than every opponent by `lead`
+    Longest { lead: usize },
+    /// Take food away from opponents, i.e. only chase food that another snake reaches at
+    /// most `margin` moves after us
+    Deny { margin: u32 },
+}
+
+impl FoodPolicy {
+    /// Parses `always`, `hungry:N`, `longest:K` or `deny[:K]`
+    pub fn parse(spec: &str) -> Result<FoodPolicy, String> {
+        let (mode, value) = match spec.split_once(':') {
+            Some((mode, value)) => (mode, Some(value)),
+            None => (spec, None),
+        };
+        match (mode, value) {
+            ("always", None) => Ok(FoodPolicy::Always),
+            ("hungry", Some(v)) => Ok(FoodPolicy::Hungry {
+                threshold: parse_num(v)?,
+            }),
+            ("longest", Some(v)) => Ok(FoodPolicy::Longest {
+                lead: parse_num(v)?,
+            }),
+            ("deny", None) => Ok(FoodPolicy::Deny { margin: 2 }),
+            ("deny", Some(v)) => Ok(FoodPolicy::Deny {
+                margin: parse_num(v)?,
+            }),
+            _ => Err(format!("Unknown food policy {}", spec)),
+        }
+    }
+
+    /// The food `snake` should head for, empty if it should not seek food this turn.
+    /// Only food we reach before every snake that would win the head-to-head is considered.
+    pub fn goals(&self, board: &Board, snake: &Battlesnake) -> Vec<Coord> {
+        let winnable = contest_food(board)
+            .into_iter()
+            .filter(|c| c.is_won_by(snake, board))
+            .collect::<Vec<FoodContest>>();
+
+        // Whatever the policy, do not starve on the way to the nearest food
+        let nearest = winnable.iter().map(|c| c.distance).min();
+        let starving =
+            nearest.is_some_and(|d| snake.health.max(0) as u32 <= d.saturating_add(HEALTH_BUFFER));
+
+        let longest_opponent = board
+            .snakes
+            .iter()
+            .filter(|s| s.id != snake.id)
+            .map(|s| s.body.len())
+            .max()
+            .unwrap_or(0);
+
+        let seek = |c: &FoodContest| match self {
+            FoodPolicy::Always => true,
+            FoodPolicy::Hungry { threshold } => starving || snake.health < *threshold,
+            FoodPolicy::Longest { lead } => starving || snake.body.len() < longest_opponent + lead,
+            FoodPolicy::Deny { margin } => starving || c.margin <= *margin,
+        };
+
+        winnable
+            .iter()
+            .filter(|c| seek(c))
+            .map(|c| c.food)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{board_with_food, snake, snake_with_health};
+
+    fn me(health: i32) -> Battlesnake {
+        snake_with_health(
+            "me",
+            vec![
+                Coord { x: 0, y: 0 },
+                Coord { x: 0, y: 1 },
+                Coord { x: 0, y: 2 },
+            ],
+            health,
+        )
+    }
+
+    fn opponent(len: i32) -> Battlesnake {
+        snake(
+            "other",
+            (0..len).map(|x| Coord { x: 10 - x, y: 10 }).collect(),
+        )
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(FoodPolicy::parse("always"), Ok(FoodPolicy::Always));
+        assert_eq!(
+            FoodPolicy::parse("hungry:40"),
+            Ok(FoodPolicy::Hungry { threshold: 40 })
+        );
+        assert_eq!(
+            FoodPolicy::parse("longest:2"),
+            Ok(FoodPolicy::Longest { lead: 2 })
+        );
+        assert_eq!(
+            FoodPolicy::parse("deny"),
+            Ok(FoodPolicy::Deny { margin: 2 })
+        );
+        assert_eq!(
+            FoodPolicy::parse("deny:0"),
+            Ok(FoodPolicy::Deny { margin: 0 })
+        );
+        assert!(FoodPolicy::parse("hungry").is_err());
+        assert!(FoodPolicy::parse("hungry:x").is_err());
+        assert!(FoodPolicy::parse("never").is_err());
+    }
+
+    #[test]
+    fn test_hungry_threshold() {
+        let food = vec![Coord { x: 3, y: 0 }];
+        let policy = FoodPolicy::Hungry { threshold: 50 };
+
+        let b = board_with_food(11, 11, vec![me(80), opponent(3)], food.clone());
+        assert!(policy.goals(&b, &me(80)).is_empty());
+
+        let b = board_with_food(11, 11, vec![me(30), opponent(3)], food.clone());
+        assert_eq!(policy.goals(&b, &me(30)), food);
+    }
+
+    #[test]
+    fn test_far_food_overrides_threshold() {
+        // 20 moves away with only 22 health left
+        let food = vec![Coord { x: 10, y: 10 }];
+        let b = board_with_food(11, 11, vec![me(22)], food.clone());
+        let policy = FoodPolicy::Hungry { threshold: 10 };
+        assert_eq!(policy.goals(&b, &me(22)), food);
+    }
+
+    #[test]
+    fn test_stay_longest_by_lead() {
+        let food = vec![Coord { x: 3, y: 0 }];
+        let policy = FoodPolicy::Longest { lead: 2 };
+
+        // Length 3 against 2 is not enough of a lead
+        let b = board_with_food(11, 11, vec![me(90), opponent(2)], food.clone());
+        assert_eq!(policy.goals(&b, &me(90)), food);
+
+        let b = board_with_food(11, 11, vec![me(90), opponent(1)], food.clone());
+        assert!(policy.goals(&b, &me(90)).is_empty());
+    }
+
+    #[test]
+    fn test_deny_only_contested_food() {
+        // We are 1 and 9 moves away, the opponent 19 and 11
+        let ours = Coord { x: 1, y: 0 };
+        let contested = Coord { x: 4, y: 5 };
+        let b = board_with_food(11, 11, vec![me(90), opponent(3)], vec![ours, contested]);
+
+        let deny = FoodPolicy::Deny { margin: 2 };
+        assert_eq!(deny.goals(&b, &me(90)), vec![contested]);
+        assert_eq!(FoodPolicy::Always.goals(&b, &me(90)), vec![ours, contested]);
+    }
+}
@@ -1,27 +1,38 @@
-use serde::{Deserialize, Serialize, Serializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::fmt::Display;
 
 // API and Response Objects
 // See https://docs.battlesnake.com/api
-#[derive(Deserialize, Serialize, Debug)]
+// Fields we can do without have defaults and unknown fields are ignored, so that API
+// changes don't make us miss a move.
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Game {
     pub id: String,
+    #[serde(default)]
     pub ruleset: HashMap<String, Value>,
+    #[serde(default = "default_timeout")]
     pub timeout: u32,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+fn default_timeout() -> u32 {
+    500
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Board {
     pub height: i32,
     pub width: i32,
+    #[serde(default)]
     pub food: Vec<Coord>,
     pub snakes: Vec<Battlesnake>,
+    #[serde(default)]
     pub hazards: Vec<Coord>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(from = "SnakeFields")]
 pub struct Battlesnake {
     pub id: String,
     pub name: String,
@@ -33,6 +44,50 @@ pub struct Battlesnake {
     pub shout: Option<String>,
 }
 
+// Battlesnake as sent, a missing length is taken from the body
+#[derive(Deserialize)]
+struct SnakeFields {
+    id: String,
+    #[serde(default)]
+    name: String,
+    health: i32,
+    body: Vec<Coord>,
+    head: Coord,
+    #[serde(default)]
+    length: Option<i32>,
+    #[serde(default, deserialize_with = "string_or_number")]
+    latency: String,
+    #[serde(default)]
+    shout: Option<String>,
+}
+
+impl From<SnakeFields> for Battlesnake {
+    fn from(s: SnakeFields) -> Battlesnake {
+        Battlesnake {
+            length: s.length.unwrap_or(s.body.len() as i32),
+            id: s.id,
+            name: s.name,
+            health: s.health,
+            body: s.body,
+            head: s.head,
+            latency: s.latency,
+            shout: s.shout,
+        }
+    }
+}
+
+// The latency is sent as a string, but some clients send a number or null
+fn string_or_number<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Value::deserialize(deserializer)? {
+        Value::String(s) => s,
+        Value::Null => String::new(),
+        other => other.to_string(),
+    })
+}
+
 #[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Copy, Clone, Ord, PartialOrd, Hash)]
 pub struct Coord {
     pub x: i32,
@@ -61,29 +116,72 @@ impl Coord {
         }
     }
 
-    pub fn neighbors(&self, board_size: (i32, i32)) -> Vec<Coord> {
+    pub fn neighbors(&self, board_size: (i32, i32)) -> impl Iterator<Item = Coord> {
+        let pos = *self;
         [
             Direction::Left,
             Direction::Right,
             Direction::Up,
             Direction::Down,
         ]
-        .iter()
-        .map(|c| self.next_coord_in_dir(c))
-        .filter(|n| n.x >= 0 && n.y >= 0 && n.x < board_size.0&& n.y < board_size.1)
-        .collect()
+        .into_iter()
+        .map(move |d| pos.next_coord_in_dir(&d))
+        .filter(move |n| n.x >= 0 && n.y >= 0 && n.x < board_size.0 && n.y < board_size.1)
     }
 }
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct GameState {
     pub game: Game,
+    #[serde(default)]
     pub turn: i32,
     pub board: Board,
     pub you: Battlesnake,
 }
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+impl GameState {
+    /// Checks what the strategies rely on: every snake has a body starting at its head and
+    /// all coordinates lie on the board.
+    pub fn validate(&self) -> Result<(), String> {
+        let board = &self.board;
+        if board.width <= 0 || board.height <= 0 {
+            return Err(format!(
+                "Invalid board size {}x{}",
+                board.width, board.height
+            ));
+        }
+        let on_board = |c: &Coord| c.x >= 0 && c.y >= 0 && c.x < board.width && c.y < board.height;
+
+        for snake in board.snakes.iter().chain([&self.you]) {
+            if snake.body.first() != Some(&snake.head) {
+                return Err(format!(
+                    "Head of snake {} is not its first body part",
+                    snake.id
+                ));
+            }
+            if let Some(c) = snake.body.iter().find(|c| !on_board(c)) {
+                return Err(format!(
+                    "Snake {} has body part {:?} off the board",
+                    snake.id, c
+                ));
+            }
+        }
+        if !board.snakes.iter().any(|s| s.id == self.you.id) {
+            return Err(format!("Snake {} is not on the board", self.you.id));
+        }
+        if let Some(c) = board
+            .food
+            .iter()
+            .chain(&board.hazards)
+            .find(|c| !on_board(c))
+        {
+            return Err(format!("Food or hazard {:?} off the board", c));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
 pub enum Direction {
     Left,
     Right,
@@ -146,3 +244,75 @@ pub struct Move {
     #[serde(rename = "move")]
     pub dir: Direction,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request() -> Value {
+        let me = json!({
+            "id": "me",
+            "name": "me",
+            "health": 90,
+            "body": [{"x": 1, "y": 1}, {"x": 1, "y": 0}],
+            "head": {"x": 1, "y": 1},
+            "length": 2,
+            "latency": "12",
+            "shout": ""
+        });
+        json!({
+            "game": {"id": "g", "ruleset": {"name": "standard"}, "timeout": 500},
+            "turn": 3,
+            "board": {"height": 5, "width": 5, "food": [], "hazards": [], "snakes": [me]},
+            "you": me
+        })
+    }
+
+    fn parse(value: Value) -> GameState {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_optional_fields_have_defaults() {
+        let mut value = request();
+        value["game"].as_object_mut().unwrap().remove("timeout");
+        value["board"].as_object_mut().unwrap().remove("hazards");
+        value["game"]["map"] = json!("standard");
+        let snake = value["you"].as_object_mut().unwrap();
+        snake.remove("shout");
+        snake.remove("name");
+        snake.remove("length");
+        snake.insert("latency".to_string(), json!(12));
+        value["board"]["snakes"][0] = value["you"].clone();
+
+        let state = parse(value);
+        assert_eq!(state.game.timeout, 500);
+        assert!(state.board.hazards.is_empty());
+        assert_eq!(state.you.latency, "12");
+        assert_eq!(state.you.shout, None);
+        assert_eq!(state.you.length, 2);
+        assert!(state.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(parse(request()).validate().is_ok());
+
+        let mut detached_head = request();
+        detached_head["you"]["head"] = json!({"x": 2, "y": 2});
+        assert!(parse(detached_head).validate().is_err());
+
+        let mut off_board = request();
+        off_board["board"]["snakes"][0]["body"][1] = json!({"x": 1, "y": -1});
+        assert!(parse(off_board).validate().is_err());
+
+        let mut food = request();
+        food["board"]["food"] = json!([{"x": 5, "y": 0}]);
+        assert!(parse(food).validate().is_err());
+
+        let mut missing = request();
+        missing["board"]["snakes"] = json!([]);
+        assert!(parse(missing).validate().is_err());
+    }
+}
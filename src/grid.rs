@@ -0,0 +1,119 @@
+use crate::game::Coord;
+use std::ops::{Index, IndexMut};
+
+/// Dense storage of one value per board cell, indexed by coordinate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid<T> {
+    width: i32,
+    height: i32,
+    cells: Vec<T>,
+}
+
+impl<T: Clone> Grid<T> {
+    pub fn new(width: i32, height: i32, value: T) -> Grid<T> {
+        Grid {
+            width,
+            height,
+            cells: vec![value; (width.max(0) * height.max(0)) as usize],
+        }
+    }
+
+    /// Grid of the given size with `value` set for every coordinate in `coords` on the board
+    pub fn with_cells(width: i32, height: i32, default: T, coords: &[Coord], value: T) -> Grid<T> {
+        let mut grid = Grid::new(width, height, default);
+        for c in coords {
+            if let Some(cell) = grid.get_mut(c) {
+                *cell = value.clone();
+            }
+        }
+        grid
+    }
+}
+
+impl<T> Grid<T> {
+    pub fn contains(&self, c: &Coord) -> bool {
+        c.x >= 0 && c.y >= 0 && c.x < self.width && c.y < self.height
+    }
+
+    fn index_of(&self, c: &Coord) -> Option<usize> {
+        self.contains(c).then(|| (c.y * self.width + c.x) as usize)
+    }
+
+    /// `None` for coordinates outside of the board
+    pub fn get(&self, c: &Coord) -> Option<&T> {
+        self.index_of(c).map(|i| &self.cells[i])
+    }
+
+    pub fn get_mut(&mut self, c: &Coord) -> Option<&mut T> {
+        self.index_of(c).map(|i| &mut self.cells[i])
+    }
+
+    /// All cells with their coordinates, row by row starting at the bottom
+    #[cfg(test)]
+    pub fn iter(&self) -> impl Iterator<Item = (Coord, &T)> {
+        let width = self.width;
+        self.cells.iter().enumerate().map(move |(i, v)| {
+            let c = Coord {
+                x: i as i32 % width,
+                y: i as i32 / width,
+            };
+            (c, v)
+        })
+    }
+}
+
+impl<T> Index<Coord> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, c: Coord) -> &T {
+        self.get(&c)
+            .unwrap_or_else(|| panic!("{:?} is outside of the grid", c))
+    }
+}
+
+impl<T> IndexMut<Coord> for Grid<T> {
+    fn index_mut(&mut self, c: Coord) -> &mut T {
+        self.get_mut(&c)
+            .unwrap_or_else(|| panic!("{:?} is outside of the grid", c))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_and_set() {
+        let mut grid = Grid::new(3, 2, 0);
+        grid[Coord { x: 2, y: 1 }] = 5;
+
+        assert_eq!(grid.get(&Coord { x: 2, y: 1 }), Some(&5));
+        assert_eq!(grid.get(&Coord { x: 1, y: 1 }), Some(&0));
+        assert_eq!(grid.get(&Coord { x: 3, y: 1 }), None);
+        assert_eq!(grid.get(&Coord { x: 0, y: -1 }), None);
+    }
+
+    #[test]
+    fn test_with_cells_ignores_off_board_coords() {
+        let grid = Grid::with_cells(
+            2,
+            2,
+            false,
+            &[Coord { x: 0, y: 1 }, Coord { x: 5, y: 5 }],
+            true,
+        );
+        let set = grid
+            .iter()
+            .filter(|(_, v)| **v)
+            .map(|(c, _)| c)
+            .collect::<Vec<_>>();
+        assert_eq!(set, vec![Coord { x: 0, y: 1 }]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_index_outside_panics() {
+        let grid = Grid::new(2, 2, 0);
+        let _ = grid[Coord { x: 2, y: 0 }];
+    }
+}
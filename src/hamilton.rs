@@ -0,0 +1,359 @@
+use crate::game::{get_direction_from_to, Battlesnake, Board, Coord, Game, Move};
+use crate::strategy::{SimpleStrategy, Strategy};
+use rand::RngCore;
+
+/// Cells of the board in the order of a Hamiltonian cycle. On boards where both sides are odd
+/// no such cycle exists, there the top right corner is left out.
+#[derive(Debug, Clone)]
+pub struct HamiltonCycle {
+    width: i32,
+    height: i32,
+    cells: Vec<Coord>,
+    /// Position of every board cell in `cells`, `None` for the skipped corner
+    order: Vec<Option<usize>>,
+    /// The skipped corner and the position of its neighbour on the cycle. The corner can be
+    /// visited instead of the cell after that neighbour, both lead to the same next cell.
+    skipped: Option<(Coord, usize)>,
+}
+
+impl HamiltonCycle {
+    pub fn new(width: i32, height: i32) -> Option<HamiltonCycle> {
+        if width < 2 || height < 2 {
+            return None;
+        }
+
+        let cells = if height % 2 == 0 {
+            serpentine(width, height)
+        } else if width % 2 == 0 {
+            serpentine(height, width)
+                .into_iter()
+                .map(|c| Coord { x: c.y, y: c.x })
+                .collect()
+        } else {
+            with_top_row_detours(serpentine(width, height - 1), width, height)
+        };
+
+        let mut order = vec![None; (width * height) as usize];
+        for (i, c) in cells.iter().enumerate() {
+            order[(c.y * width + c.x) as usize] = Some(i);
+        }
+        let corner = Coord {
+            x: width - 1,
+            y: height - 1,
+        };
+        let skipped = order[(corner.y * width + corner.x) as usize]
+            .is_none()
+            .then(|| {
+                let entry = Coord {
+                    x: width - 1,
+                    y: height - 2,
+                };
+                (corner, order[(entry.y * width + entry.x) as usize].unwrap())
+            });
+
+        Some(HamiltonCycle {
+            width,
+            height,
+            cells,
+            order,
+            skipped,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn index_of(&self, c: &Coord) -> Option<usize> {
+        if c.x < 0 || c.y < 0 || c.x >= self.width || c.y >= self.height {
+            return None;
+        }
+        self.order[(c.y * self.width + c.x) as usize]
+    }
+
+    /// Position on the cycle, the skipped corner takes the place of the cell it replaces
+    pub fn position(&self, c: &Coord) -> Option<usize> {
+        match self.skipped {
+            Some((corner, entry)) if corner == *c => Some((entry + 1) % self.cells.len()),
+            _ => self.index_of(c),
+        }
+    }
+
+    pub fn next(&self, c: &Coord) -> Option<Coord> {
+        self.position(c)
+            .map(|i| self.cells[(i + 1) % self.cells.len()])
+    }
+
+    /// When `c` is the cell before the skipped corner, the corner and the cell it replaces
+    pub fn corner_detour(&self, c: &Coord) -> Option<(Coord, Coord)> {
+        let (corner, entry) = self.skipped?;
+        (self.index_of(c) == Some(entry))
+            .then(|| (corner, self.cells[(entry + 1) % self.cells.len()]))
+    }
+
+    /// Number of steps along the cycle from `from` to `to`
+    pub fn distance(&self, from: usize, to: usize) -> usize {
+        (to + self.cells.len() - from) % self.cells.len()
+    }
+}
+
+// Cycle for an even height: along the bottom row to the right, snake up through columns
+// 1.. and return down column 0.
+fn serpentine(width: i32, height: i32) -> Vec<Coord> {
+    let mut cells = (0..width).map(|x| Coord { x, y: 0 }).collect::<Vec<_>>();
+    for y in 1..height {
+        if y % 2 == 1 {
+            cells.extend((1..width).rev().map(|x| Coord { x, y }));
+        } else {
+            cells.extend((1..width).map(|x| Coord { x, y }));
+        }
+    }
+    cells.extend((1..height).rev().map(|y| Coord { x: 0, y }));
+    cells
+}
+
+// Extends a cycle over the lower `height - 1` rows by the top row, except for its last cell.
+// Every horizontal edge (x, h-2) - (x+1, h-2) for even x is replaced by a detour through
+// (x, h-1) and (x+1, h-1).
+fn with_top_row_detours(cycle: Vec<Coord>, width: i32, height: i32) -> Vec<Coord> {
+    let top = height - 1;
+    let mut cells = Vec::with_capacity(cycle.len() + width as usize);
+    for (i, c) in cycle.iter().enumerate() {
+        cells.push(*c);
+        let next = cycle[(i + 1) % cycle.len()];
+        if c.y == top - 1 && next.y == top - 1 && c.x.min(next.x) % 2 == 0 {
+            cells.push(Coord { x: c.x, y: top });
+            cells.push(Coord { x: next.x, y: top });
+        }
+    }
+    cells
+}
+
+/// Solo strategy that follows a Hamiltonian cycle and therefore never traps itself. While the
+/// body is short it takes shortcuts towards food that never pass its own tail.
+pub struct HamiltonStrategy;
+
+// Free cells kept in front of the head on top of the body length when taking a shortcut,
+// so that the snake can still grow while its tail catches up
+const SHORTCUT_MARGIN: usize = 3;
+
+impl HamiltonStrategy {
+    fn choose(&self, cycle: &HamiltonCycle, board: &Board, snake: &Battlesnake) -> Option<Coord> {
+        let n = cycle.len();
+        let head_idx = cycle.position(&snake.head)?;
+        let tail_idx = cycle.position(snake.body.last()?)?;
+        let to_tail = match cycle.distance(head_idx, tail_idx) {
+            0 => n,
+            d => d,
+        };
+
+        // Food in the skipped corner is eaten by visiting it instead of the next cell
+        let occupied = &snake.body[..snake.body.len() - 1];
+        let detour = cycle
+            .corner_detour(&snake.head)
+            .filter(|(corner, replaced)| {
+                board.food.contains(corner)
+                    && !occupied.contains(corner)
+                    && !occupied.contains(replaced)
+            })
+            .map(|(corner, _)| corner);
+        let follow = detour.or_else(|| cycle.next(&snake.head).filter(|c| !occupied.contains(c)));
+        if snake.body.len() * 2 >= n {
+            return follow;
+        }
+
+        let food_dist = |c: &Coord| -> usize {
+            board
+                .food
+                .iter()
+                .filter_map(|f| {
+                    if f == c {
+                        return Some(0);
+                    }
+                    let from = cycle.position(c)?;
+                    let to = cycle.position(f)?;
+                    Some(cycle.distance(from, to))
+                })
+                .min()
+                .unwrap_or(n)
+        };
+
+        snake
+            .head
+            .neighbors((board.width, board.height))
+            .filter(|c| !snake.body.contains(c))
+            // The skipped corner is only entered by the detour
+            .filter(|c| match cycle.index_of(c) {
+                Some(idx) => {
+                    cycle.distance(head_idx, idx) + snake.body.len() + SHORTCUT_MARGIN < to_tail
+                }
+                None => false,
+            })
+            .chain(follow)
+            .min_by_key(|c| food_dist(c))
+    }
+}
+
+impl Strategy for HamiltonStrategy {
+    fn make_move(
+        &self,
+        game: &Game,
+        board: &Board,
+        snake: &Battlesnake,
+        rng: &mut dyn RngCore,
+    ) -> Move {
+        // The cycle only guarantees safety if nobody else is on the board
+        if board.snakes.len() > 1 {
+            return SimpleStrategy::default().make_move(game, board, snake, rng);
+        }
+
+        let next = HamiltonCycle::new(board.width, board.height)
+            .and_then(|cycle| self.choose(&cycle, board, snake))
+            .and_then(|c| get_direction_from_to(&snake.head, &c));
+
+        match next {
+            Some(dir) => Move { dir },
+            None => SimpleStrategy::default().make_move(game, board, snake, rng),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::{initial_board, step, Ruleset};
+    use crate::seeding::{game_rng, move_rng};
+    use crate::simulation::simulation_game;
+    use std::collections::HashMap;
+
+    fn assert_valid_cycle(width: i32, height: i32) {
+        let cycle = HamiltonCycle::new(width, height).unwrap();
+        let expected_len = if width % 2 == 1 && height % 2 == 1 {
+            width * height - 1
+        } else {
+            width * height
+        };
+        assert_eq!(cycle.len(), expected_len as usize, "{}x{}", width, height);
+
+        let mut seen = cycle.cells.clone();
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), cycle.len(), "{}x{}", width, height);
+
+        for (i, c) in cycle.cells.iter().enumerate() {
+            let next = cycle.cells[(i + 1) % cycle.len()];
+            assert!(
+                get_direction_from_to(c, &next).is_some(),
+                "{}x{}: {:?} -> {:?}",
+                width,
+                height,
+                c,
+                next
+            );
+        }
+    }
+
+    #[test]
+    fn test_cycles_are_hamiltonian() {
+        for width in 2..12 {
+            for height in 2..12 {
+                assert_valid_cycle(width, height);
+            }
+        }
+        assert_valid_cycle(60, 60);
+        assert_valid_cycle(15, 15);
+    }
+
+    #[test]
+    fn test_no_cycle_on_single_row() {
+        assert!(HamiltonCycle::new(1, 10).is_none());
+        assert!(HamiltonCycle::new(10, 1).is_none());
+    }
+
+    #[test]
+    fn test_detour_through_skipped_corner() {
+        let cycle = HamiltonCycle::new(7, 7).unwrap();
+        let corner = Coord { x: 6, y: 6 };
+        let entry = Coord { x: 6, y: 5 };
+        let exit = Coord { x: 5, y: 6 };
+        assert_eq!(cycle.next(&corner), Some(exit));
+        assert_eq!(
+            cycle.next(&cycle.corner_detour(&entry).unwrap().1),
+            Some(exit)
+        );
+
+        // A long snake standing before the corner
+        let mut body = vec![entry];
+        let mut c = entry;
+        for _ in 0..30 {
+            c = cycle.cells[(cycle.index_of(&c).unwrap() + cycle.len() - 1) % cycle.len()];
+            body.push(c);
+        }
+        let snake = Battlesnake {
+            id: "me".to_string(),
+            name: "me".to_string(),
+            health: 100,
+            head: entry,
+            length: body.len() as i32,
+            body,
+            latency: "0".to_string(),
+            shout: None,
+        };
+        let mut board = Board {
+            height: 7,
+            width: 7,
+            food: vec![],
+            snakes: vec![snake.clone()],
+            hazards: vec![],
+        };
+        let strategy = HamiltonStrategy;
+        assert_eq!(strategy.choose(&cycle, &board, &snake), cycle.next(&entry));
+        board.food = vec![corner];
+        assert_eq!(strategy.choose(&cycle, &board, &snake), Some(corner));
+
+        // Standing in the corner continues along the cycle
+        let mut moved = snake.clone();
+        moved.body.insert(0, corner);
+        moved.body.pop();
+        moved.head = corner;
+        assert_eq!(strategy.choose(&cycle, &board, &moved), Some(exit));
+    }
+
+    #[test]
+    fn test_solo_games_only_die_on_a_full_board() {
+        let game = simulation_game("solo");
+        let ruleset = Ruleset::default();
+        for size in [6, 7, 10] {
+            let cycle = HamiltonCycle::new(size, size).unwrap();
+            let mut rng = game_rng(size as u64, &game.id);
+            let mut board = initial_board(size, size, &["hamilton"], &mut rng);
+
+            for turn in 0..3000 {
+                let snake = board.snakes[0].clone();
+                let mut move_rng = move_rng(size as u64, &game.id, &snake.id, turn);
+                let dir = HamiltonStrategy
+                    .make_move(&game, &board, &snake, &mut move_rng)
+                    .dir;
+                let moves = HashMap::from([(snake.id.clone(), dir)]);
+                let eliminated = step(&mut board, &moves, &ruleset, &mut rng);
+
+                if let Some((_, cause)) = eliminated.first() {
+                    let mut occupied = snake.body.clone();
+                    occupied.sort();
+                    occupied.dedup();
+                    // Starving is only possible once no cell is left for food
+                    assert!(
+                        occupied.len() >= cycle.len(),
+                        "{}x{}: {} after {} turns with length {}",
+                        size,
+                        size,
+                        cause,
+                        turn,
+                        snake.body.len()
+                    );
+                    break;
+                }
+            }
+        }
+    }
+}
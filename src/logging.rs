@@ -0,0 +1,90 @@
+use env_logger::{Env, Target};
+use log::kv::{self, Key, Value, VisitSource};
+use log::Record;
+use serde_json::{Map, Number};
+use std::io::Write;
+
+// Our own records down to info, everything else (e.g. Rocket) only from warnings on.
+// `RUST_LOG` overrides it.
+const DEFAULT_FILTER: &str = "warn,battlesnake_exploration=info";
+
+struct JsonFields<'a>(&'a mut Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let json = if let Some(b) = value.to_bool() {
+            serde_json::Value::Bool(b)
+        } else if let Some(n) = value.to_i64() {
+            serde_json::Value::from(n)
+        } else if let Some(n) = value.to_u64() {
+            serde_json::Value::from(n)
+        } else if let Some(n) = value.to_f64().and_then(Number::from_f64) {
+            serde_json::Value::Number(n)
+        } else {
+            serde_json::Value::String(value.to_string())
+        };
+        self.0.insert(key.to_string(), json);
+        Ok(())
+    }
+}
+
+/// The record as a flat JSON object, its key-value pairs next to level, target and message
+pub fn json_record(record: &Record) -> serde_json::Value {
+    let mut fields = Map::new();
+    fields.insert("level".to_string(), record.level().as_str().into());
+    fields.insert("target".to_string(), record.target().into());
+    fields.insert("msg".to_string(), record.args().to_string().into());
+    let _ = record.key_values().visit(&mut JsonFields(&mut fields));
+    serde_json::Value::Object(fields)
+}
+
+/// Logs one JSON object per line to stdout
+pub fn init() {
+    let _ = env_logger::Builder::from_env(Env::default().default_filter_or(DEFAULT_FILTER))
+        .target(Target::Stdout)
+        .format(|buf, record| {
+            let mut json = json_record(record);
+            json["ts"] = buf.timestamp_micros().to_string().into();
+            writeln!(buf, "{}", json)
+        })
+        .try_init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn test_key_values_keep_their_type() {
+        let kvs: &[(&str, Value)] = &[
+            ("game_id", Value::from("g1")),
+            ("turn", Value::from(7)),
+            ("nodes", Value::from(u64::MAX)),
+            ("decision_ms", Value::from(12.5)),
+            ("won", Value::from(true)),
+        ];
+        let json = json_record(
+            &Record::builder()
+                .level(Level::Info)
+                .target("battlesnake_exploration")
+                .args(format_args!("move"))
+                .key_values(&kvs)
+                .build(),
+        );
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "level": "INFO",
+                "target": "battlesnake_exploration",
+                "msg": "move",
+                "game_id": "g1",
+                "turn": 7,
+                "nodes": u64::MAX,
+                "decision_ms": 12.5,
+                "won": true,
+            })
+        );
+    }
+}
@@ -1,19 +1,59 @@
 #[macro_use]
 extern crate rocket;
+mod chokepoint;
+mod config;
+mod cutoff;
+mod duel;
+mod evaluation;
 mod flood_fill;
+mod food_contest;
+mod food_policy;
 mod game;
+mod grid;
+mod hamilton;
+mod logging;
+mod mcts;
+mod metrics;
+mod move_filter;
+mod observe;
+mod parallel;
 mod pathfinding;
+mod ponder;
+mod rules;
+mod safe_move;
+mod seeding;
+mod simulation;
 mod strategy;
+mod survival;
+#[cfg(test)]
+mod test_support;
+mod tournament;
+mod transposition;
+mod tuner;
 mod utils;
+mod zobrist;
 
-use crate::game::{GameState, Move};
-use crate::strategy::{RandomStrategy, SimpleStrategy, Strategy, StrategyState};
+use crate::config::ServerConfig;
+use crate::game::{Direction, GameState, Move};
+use crate::metrics::{Fallback, Metrics};
+use crate::move_filter::is_wrapped;
+use crate::parallel::{search_time, Deadline};
+use crate::ponder::{predict_next_board, Prediction, Sessions};
+use crate::rules::elimination_cause;
+use crate::safe_move::{safe_move, salvage_move};
+use crate::seeding::move_rng;
+use crate::strategy::{build_strategy, StrategyState};
+use crate::tournament::TournamentConfig;
+use crate::tuner::TunerConfig;
 use crate::utils::info;
-use rocket::http::Status;
-use rocket::serde::json::Json;
-use rocket::State;
-use serde_json::Value;
+use rocket::http::{ContentType, Status};
+use rocket::serde::json::{self, Json};
+use rocket::tokio::{task, time};
+use rocket::{Build, Rocket, State};
+use serde_json::{json, Value};
 use std::env;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
 #[get("/")]
 fn handle_info() -> Json<Value> {
@@ -21,51 +61,392 @@ fn handle_info() -> Json<Value> {
     Json(info())
 }
 
+#[get("/health")]
+fn handle_health() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+#[get("/status")]
+fn handle_status(
+    strategy: &State<StrategyState>,
+    config: &State<ServerConfig>,
+    sessions: &State<Sessions>,
+    metrics: &State<Metrics>,
+) -> Json<Value> {
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "strategy": strategy.name,
+        "strategy_config": strategy.config,
+        "config": config.inner(),
+        "uptime_seconds": metrics.uptime().as_secs(),
+        "active_games": sessions.active_games(),
+    }))
+}
+
+#[get("/metrics")]
+fn handle_metrics(metrics: &State<Metrics>, sessions: &State<Sessions>) -> (ContentType, String) {
+    let content_type = ContentType::new("text", "plain").with_params(("version", "0.0.4"));
+    (content_type, metrics.render(sessions.active_games().len()))
+}
+
 #[post("/start", format = "json", data = "<game_state>")]
-fn handle_start(game_state: Json<GameState>) -> Status {
-    // TODO
-    info!("START");
+fn handle_start(
+    game_state: Result<Json<GameState>, json::Error<'_>>,
+    sessions: &State<Sessions>,
+) -> Status {
+    // Nothing depends on the start request, a game we could not read starts with its first move
+    let game_state = match game_state {
+        Ok(game_state) => game_state,
+        Err(e) => {
+            error!(error:% = e; "malformed start request");
+            return Status::Ok;
+        }
+    };
+    info!(
+        game_id = game_state.game.id.as_str(),
+        snake_id = game_state.you.id.as_str();
+        "start"
+    );
+    sessions.start(&game_state.game, &game_state.you);
     Status::Ok
 }
 
+// Share of the game timeout after which a still running search is abandoned
+const MOVE_TIMEOUT_SHARE: f64 = 0.8;
+
+// Instant move used when the strategy does not finish in time
+fn fallback_move(game_state: &GameState) -> Move {
+    Move {
+        dir: safe_move(
+            &game_state.board,
+            &game_state.you,
+            is_wrapped(&game_state.game),
+        ),
+    }
+}
+
+// Move for requests in which not even our head and the board size can be read. Without a
+// board to look at any direction is as good as another, this one is fixed so it shows up
+// in replays.
+const BLIND_MOVE: Direction = Direction::Down;
+
+// Answers requests we can't make sense of, a rejected request would cost us the move.
+fn invalid_request_move(metrics: &Metrics, received: Instant, dir: Direction) -> Json<Move> {
+    metrics.record_fallback(Fallback::InvalidRequest);
+    metrics.record_move(received.elapsed());
+    Json(Move { dir })
+}
+
 #[post("/move", format = "json", data = "<game_state>")]
-fn handle_move(game_state: Json<GameState>, strategy: &State<StrategyState>) -> Json<Move> {
-    // TODO
-    println!("turn: {}", game_state.turn);
-    info!("MOVE");
-    Json(
-        strategy
-            .strategy
-            .make_move(&game_state.game, &game_state.board, &game_state.you),
-    )
+async fn handle_move(
+    game_state: Result<Json<GameState>, json::Error<'_>>,
+    strategy: &State<StrategyState>,
+    config: &State<ServerConfig>,
+    sessions: &State<Sessions>,
+    metrics: &State<Metrics>,
+) -> Json<Move> {
+    let received = Instant::now();
+    let game_state = match game_state {
+        Ok(game_state) => game_state.into_inner(),
+        Err(e) => {
+            error!(error:% = e; "malformed move request");
+            let dir = match e {
+                json::Error::Parse(request, _) => salvage_move(request),
+                json::Error::Io(_) => None,
+            };
+            return invalid_request_move(metrics, received, dir.unwrap_or(BLIND_MOVE));
+        }
+    };
+    if let Err(e) = game_state.validate() {
+        error!(
+            game_id = game_state.game.id.as_str(),
+            turn = game_state.turn,
+            error = e.as_str();
+            "invalid game state"
+        );
+        let wrap = is_wrapped(&game_state.game);
+        let dir = safe_move(&game_state.board, &game_state.you, wrap);
+        return invalid_request_move(metrics, received, dir);
+    }
+    let game_state = Arc::new(game_state);
+    sessions.start(&game_state.game, &game_state.you);
+    let seed = config.seed;
+    let time_limit =
+        Duration::from_millis((game_state.game.timeout as f64 * MOVE_TIMEOUT_SHARE) as u64);
+
+    let prediction = sessions.take(&game_state.game, &game_state.you);
+    let (search, deadline, started) = match prediction {
+        // The opponents moved as predicted, the search has been running since the last move
+        // and gets the usual search time on top
+        Some(p) if p.matches(&game_state.board) => {
+            let search_time = search_time(&game_state.game);
+            let deadline = p.deadline.clone();
+            task::spawn(async move {
+                time::sleep(search_time).await;
+                deadline.cancel();
+            });
+            (p.search, p.deadline, p.started)
+        }
+        other => {
+            if let Some(p) = other {
+                p.deadline.cancel();
+            }
+            // The search runs on the blocking pool, so slow strategies don't stall other games
+            let deadline = Deadline::for_turn(&game_state.game);
+            let search = {
+                let game_state = game_state.clone();
+                let strategy = strategy.strategy.clone();
+                let deadline = deadline.clone();
+                task::spawn_blocking(move || {
+                    let mut rng = move_rng(
+                        seed,
+                        &game_state.game.id,
+                        &game_state.you.id,
+                        game_state.turn,
+                    );
+                    Some(strategy.make_move_until(
+                        &game_state.game,
+                        &game_state.board,
+                        &game_state.you,
+                        &mut rng,
+                        &deadline,
+                    ))
+                })
+            };
+            (search, deadline, received)
+        }
+    };
+
+    let (mv, fallback) = match time::timeout(time_limit, search).await {
+        Ok(Ok(Some(mv))) => {
+            metrics.record_search(deadline.nodes(), started.elapsed());
+            (mv, None)
+        }
+        // Only predictions we don't survive have no move, they never match a board
+        Ok(Ok(None)) => (fallback_move(&game_state), Some(Fallback::Error)),
+        Ok(Err(e)) => {
+            warn!(
+                game_id = game_state.game.id.as_str(),
+                turn = game_state.turn,
+                error:% = e;
+                "move computation failed"
+            );
+            (fallback_move(&game_state), Some(Fallback::Error))
+        }
+        Err(_) => {
+            // Stop a search that polls the deadline, nobody waits for its result anymore
+            deadline.cancel();
+            warn!(
+                game_id = game_state.game.id.as_str(),
+                turn = game_state.turn,
+                time_limit_ms = time_limit.as_secs_f64() * 1e3;
+                "move computation exceeded the time limit"
+            );
+            (fallback_move(&game_state), Some(Fallback::Timeout))
+        }
+    };
+    let decision_time = received.elapsed();
+    info!(
+        game_id = game_state.game.id.as_str(),
+        turn = game_state.turn,
+        snake_id = game_state.you.id.as_str(),
+        strategy = strategy.name.as_str(),
+        direction:% = mv.dir,
+        decision_ms = decision_time.as_secs_f64() * 1e3,
+        nodes = deadline.nodes(),
+        fallback = fallback.map_or("none", Fallback::label);
+        "move"
+    );
+
+    if let Some(reason) = fallback {
+        metrics.record_fallback(reason);
+    }
+    if config.ponder {
+        ponder(&game_state, mv.dir, strategy, seed, sessions);
+    }
+    metrics.record_move(decision_time);
+    Json(mv)
+}
+
+// Starts searching the board we expect next turn, until the next move arrives. Predicting
+// the opponents' replies runs strategies as well, so it happens on the blocking pool too.
+fn ponder(
+    game_state: &Arc<GameState>,
+    dir: Direction,
+    strategy: &StrategyState,
+    seed: u64,
+    sessions: &Sessions,
+) {
+    let board = Arc::new(OnceLock::new());
+    let deadline = Deadline::never();
+    let search = {
+        let game_state = game_state.clone();
+        let board = board.clone();
+        let strategy = strategy.strategy.clone();
+        let deadline = deadline.clone();
+        task::spawn_blocking(move || {
+            let predicted = predict_next_board(
+                &game_state.game,
+                &game_state.board,
+                &game_state.you,
+                dir,
+                seed,
+                game_state.turn,
+            )?;
+            let you = predicted
+                .snakes
+                .iter()
+                .find(|s| s.id == game_state.you.id)?
+                .clone();
+            let board = board.get_or_init(|| predicted);
+            let turn = game_state.turn + 1;
+            let mut rng = move_rng(seed, &game_state.game.id, &you.id, turn);
+            Some(strategy.make_move_until(&game_state.game, board, &you, &mut rng, &deadline))
+        })
+    };
+    sessions.insert(
+        &game_state.game,
+        &game_state.you,
+        Prediction {
+            board,
+            deadline,
+            started: Instant::now(),
+            search,
+        },
+    );
 }
 
 #[post("/end", format = "json", data = "<game_state>")]
-fn handle_game_over(game_state: Json<GameState>) -> Status {
-    // TODO
-    info!("GAME OVER");
+fn handle_game_over(
+    game_state: Result<Json<GameState>, json::Error<'_>>,
+    strategy: &State<StrategyState>,
+    sessions: &State<Sessions>,
+    metrics: &State<Metrics>,
+) -> Status {
+    // Without the game ids there is nothing to clean up, the session expires on its own
+    let game_state = match game_state {
+        Ok(game_state) => game_state,
+        Err(e) => {
+            error!(error:% = e; "malformed end request");
+            return Status::Ok;
+        }
+    };
+    sessions.end(&game_state.game, &game_state.you);
+    strategy.strategy.game_over(&game_state.game, &game_state.you);
+    // Eliminated snakes are no longer on the board
+    let won = game_state
+        .board
+        .snakes
+        .iter()
+        .any(|s| s.id == game_state.you.id);
+    metrics.game_ended(won);
+    // The final board does not always show why we were eliminated, e.g. after a head-to-head
+    // that removed both snakes
+    let death_cause = match elimination_cause(&game_state.board, &game_state.you) {
+        Some(cause) => cause.to_string(),
+        None if won => "none".to_string(),
+        None => "unknown".to_string(),
+    };
+    info!(
+        game_id = game_state.game.id.as_str(),
+        turn = game_state.turn,
+        snake_id = game_state.you.id.as_str(),
+        strategy = strategy.name.as_str(),
+        won = won,
+        death_cause = death_cause.as_str();
+        "end"
+    );
     Status::Ok
 }
 
-#[launch]
-fn rocket() -> _ {
-    // env_logger::init();
-
-    let strategy_choice = env::args().nth(1).expect("No strategy choice given");
+fn build_rocket(strategy_choice: &str) -> Rocket<Build> {
+    logging::init();
 
-    let strategy: Box<dyn Strategy + Send + Sync> = match strategy_choice.as_str() {
-        "random" => Box::new(RandomStrategy),
-        "simple" => Box::new(SimpleStrategy),
-        _ => panic!("Strategy {} not specified", strategy_choice),
+    let strategy = build_strategy(strategy_choice).unwrap_or_else(|e| panic!("{}", e));
+    let (strategy_name, strategy_config) = match strategy_choice.split_once(':') {
+        Some((name, config)) => (name, Some(config.to_string())),
+        None => (strategy_choice, None),
     };
 
     rocket::build()
         .attach(rocket::fairing::AdHoc::on_liftoff("Startup msg", |_| {
             Box::pin(async move { info!("Battlesnake server started...") })
         }))
-        .manage(StrategyState { strategy })
+        .attach(rocket::fairing::AdHoc::config::<ServerConfig>())
+        .manage(StrategyState {
+            name: strategy_name.to_string(),
+            config: strategy_config,
+            strategy: Arc::from(strategy),
+        })
+        .manage(Sessions::default())
+        .manage(Metrics::new(strategy_name))
         .mount(
             "/",
-            routes![handle_info, handle_start, handle_move, handle_game_over],
+            routes![
+                handle_info,
+                handle_start,
+                handle_move,
+                handle_game_over,
+                handle_metrics,
+                handle_health,
+                handle_status
+            ],
         )
 }
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let choice = args.first().expect("No strategy choice given");
+
+    if choice == "tournament" {
+        match TournamentConfig::from_args(&args[1..]).and_then(|c| tournament::run(&c)) {
+            Ok(summary) => {
+                tournament::print_standings(&summary.ratings);
+                tournament::print_death_causes(&summary.death_causes);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if choice == "tune" {
+        match TunerConfig::from_args(&args[1..]).and_then(|c| tuner::tune(&c)) {
+            Ok(weights) => println!("best weights: {:?}", weights),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let _ = rocket::execute(build_rocket(choice).launch());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::blocking::Client;
+
+    #[test]
+    fn test_end_with_empty_body() {
+        let client = Client::tracked(build_rocket("random")).unwrap();
+        let me = json!({"id": "me", "health": 50, "body": [], "head": {"x": 1, "y": 1}});
+        let request = json!({
+            "game": {"id": "g"},
+            "turn": 7,
+            "board": {"width": 5, "height": 5, "snakes": []},
+            "you": me,
+        });
+        let response = client
+            .post("/end")
+            .header(ContentType::JSON)
+            .body(request.to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(client.get("/health").dispatch().status(), Status::Ok);
+    }
+}
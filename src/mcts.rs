@@ -0,0 +1,506 @@
+use crate::duel::candidate_moves;
+use crate::game::{Board, Direction};
+use crate::observe::observed_moves;
+use crate::parallel::Deadline;
+use crate::ponder::same_position;
+use crate::rules::{step, Ruleset};
+use rand::prelude::IndexedRandom;
+use rand::RngCore;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Exploration constant of UCB1
+const EXPLORATION: f64 = 1.4;
+// Turns played randomly after a new node before the board is scored
+const ROLLOUT_DEPTH: usize = 10;
+// Trees of games that ended without notice are dropped once unused for this long, or once the
+// cache is full and they are the least recently used
+const MAX_TREES: usize = 64;
+const MAX_TREE_AGE: Duration = Duration::from_secs(60);
+// Every node holds a board, this bounds the memory of a single tree
+const MAX_NODES: usize = 100_000;
+
+#[derive(Debug, Clone, Default)]
+struct MoveStats {
+    visits: u32,
+    reward: f64,
+}
+
+/// A snake alive on the board of a node with the statistics of each of its moves.
+#[derive(Debug, Clone)]
+struct Player {
+    id: String,
+    moves: Vec<Direction>,
+    stats: Vec<MoveStats>,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    board: Board,
+    players: Vec<Player>,
+    /// Child for each joint move tried so far, as indices into the moves of `players`
+    children: HashMap<Vec<usize>, usize>,
+}
+
+impl Node {
+    fn new(board: Board) -> Node {
+        let players = if board.snakes.len() < 2 {
+            // The game is over, nobody has to move
+            vec![]
+        } else {
+            board
+                .snakes
+                .iter()
+                .map(|s| {
+                    let moves = candidate_moves(&board, s);
+                    Player {
+                        id: s.id.clone(),
+                        stats: vec![MoveStats::default(); moves.len()],
+                        moves,
+                    }
+                })
+                .collect()
+        };
+        Node {
+            board,
+            players,
+            children: HashMap::new(),
+        }
+    }
+
+    fn joint_move(&self, indices: &[usize]) -> HashMap<String, Direction> {
+        self.players
+            .iter()
+            .zip(indices)
+            .map(|(p, i)| (p.id.clone(), p.moves[*i]))
+            .collect()
+    }
+}
+
+// Score of `board` for the snake `id`: nothing if it was eliminated, everything if it is the
+// last one standing and an even share otherwise
+fn reward(board: &Board, id: &str) -> f64 {
+    if !board.snakes.iter().any(|s| s.id == id) {
+        0.0
+    } else {
+        1.0 / board.snakes.len() as f64
+    }
+}
+
+// Index of the move with the best upper confidence bound, untried moves first
+fn select(player: &Player, parent_visits: u32) -> usize {
+    let log_visits = (parent_visits.max(1) as f64).ln();
+    let ucb = |s: &MoveStats| {
+        if s.visits == 0 {
+            return f64::INFINITY;
+        }
+        let n = s.visits as f64;
+        s.reward / n + EXPLORATION * (log_visits / n).sqrt()
+    };
+    player
+        .stats
+        .iter()
+        .enumerate()
+        .max_by(|a, b| ucb(a.1).total_cmp(&ucb(b.1)).then(b.0.cmp(&a.0)))
+        .map_or(0, |(i, _)| i)
+}
+
+/// Monte Carlo tree search over simultaneous moves. Every snake picks its move at a node
+/// independently by UCB1 on its own statistics (decoupled UCT). New nodes are scored by a
+/// random rollout. The tree can be kept between turns, see [`SearchTree::advance`].
+#[derive(Debug, Clone)]
+pub struct SearchTree {
+    nodes: Vec<Node>,
+    root: usize,
+    // Food spawns are not simulated, so the boards of the tree can be compared to real ones
+    ruleset: Ruleset,
+    max_nodes: usize,
+}
+
+impl SearchTree {
+    pub fn new(board: &Board) -> SearchTree {
+        SearchTree {
+            nodes: vec![Node::new(board.clone())],
+            root: 0,
+            ruleset: Ruleset::no_spawn(),
+            max_nodes: MAX_NODES,
+        }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.nodes[self.root].board
+    }
+
+    /// Number of simulations through the root
+    pub fn visits(&self) -> u32 {
+        self.nodes[self.root]
+            .players
+            .first()
+            .map_or(0, |p| p.stats.iter().map(|s| s.visits).sum())
+    }
+
+    /// Moves the root to the child reached by the moves the snakes actually made, keeping
+    /// the statistics gathered below it. Returns `false` if no child matches `board`, e.g.
+    /// because food spawned or the opponents made a move that was never searched.
+    pub fn advance(&mut self, board: &Board) -> bool {
+        let root = &self.nodes[self.root];
+        let observed = observed_moves(&root.board, board);
+        let child = root.children.iter().find_map(|(indices, child)| {
+            let consistent = root.players.iter().zip(indices).all(|(p, i)| {
+                observed
+                    .get(&p.id)
+                    .is_none_or(|observed| *observed == p.moves[*i])
+            });
+            (consistent && same_position(&self.nodes[*child].board, board)).then_some(*child)
+        });
+        match child {
+            Some(child) => {
+                self.reroot(child);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Drops every node not below `new_root`
+    fn reroot(&mut self, new_root: usize) {
+        let mut order = vec![new_root];
+        let mut remap = HashMap::from([(new_root, 0)]);
+        let mut i = 0;
+        while i < order.len() {
+            for child in self.nodes[order[i]].children.values() {
+                if !remap.contains_key(child) {
+                    remap.insert(*child, order.len());
+                    order.push(*child);
+                }
+            }
+            i += 1;
+        }
+
+        let mut old_nodes = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+        self.nodes = order
+            .into_iter()
+            .map(|old| {
+                let mut node = old_nodes[old].take().expect("every node is reached once");
+                for child in node.children.values_mut() {
+                    *child = remap[child];
+                }
+                node
+            })
+            .collect();
+        self.root = 0;
+    }
+
+    /// Runs simulations until `iterations` more have been done, `deadline` expires or the tree
+    /// is full.
+    pub fn search(&mut self, iterations: usize, deadline: &Deadline, rng: &mut dyn RngCore) {
+        let mut done = 0;
+        while done < iterations && self.nodes.len() < self.max_nodes && !deadline.expired() {
+            self.iterate(rng);
+            done += 1;
+        }
+        deadline.add_nodes(done as u64);
+    }
+
+    fn iterate(&mut self, rng: &mut dyn RngCore) {
+        let mut path = vec![];
+        let mut node = self.root;
+        let board = loop {
+            if self.nodes[node].players.is_empty() {
+                break self.nodes[node].board.clone();
+            }
+            let parent_visits = self.nodes[node].players[0]
+                .stats
+                .iter()
+                .map(|s| s.visits)
+                .sum();
+            let indices = self.nodes[node]
+                .players
+                .iter()
+                .map(|p| select(p, parent_visits))
+                .collect::<Vec<_>>();
+            path.push((node, indices.clone()));
+
+            if let Some(child) = self.nodes[node].children.get(&indices) {
+                node = *child;
+                continue;
+            }
+            let mut board = self.nodes[node].board.clone();
+            let moves = self.nodes[node].joint_move(&indices);
+            step(&mut board, &moves, &self.ruleset, rng);
+            self.nodes.push(Node::new(board.clone()));
+            let child = self.nodes.len() - 1;
+            self.nodes[node].children.insert(indices, child);
+            break self.rollout(board, rng);
+        };
+
+        for (node, indices) in path {
+            for (player, i) in self.nodes[node].players.iter_mut().zip(indices) {
+                let stats = &mut player.stats[i];
+                stats.visits += 1;
+                stats.reward += reward(&board, &player.id);
+            }
+        }
+    }
+
+    fn rollout(&self, mut board: Board, rng: &mut dyn RngCore) -> Board {
+        for _ in 0..ROLLOUT_DEPTH {
+            if board.snakes.len() < 2 {
+                break;
+            }
+            let moves = board
+                .snakes
+                .iter()
+                .map(|s| {
+                    let dir = *candidate_moves(&board, s).choose(rng).unwrap();
+                    (s.id.clone(), dir)
+                })
+                .collect::<HashMap<_, _>>();
+            step(&mut board, &moves, &self.ruleset, rng);
+        }
+        board
+    }
+
+    /// The most visited move of snake `id` at the root, with its visits
+    pub fn best_move(&self, id: &str) -> Option<(Direction, u32)> {
+        let player = self.nodes[self.root].players.iter().find(|p| p.id == id)?;
+        player
+            .moves
+            .iter()
+            .zip(&player.stats)
+            .max_by_key(|(_, s)| s.visits)
+            .map(|(d, s)| (*d, s.visits))
+    }
+}
+
+struct TreeSlot {
+    used: Instant,
+    /// Number of searches started for the key, only the latest may store its tree
+    searches: u64,
+    /// `None` while a search is running on it
+    tree: Option<SearchTree>,
+}
+
+/// Search trees kept between turns, e.g. one per game and snake.
+#[derive(Default)]
+pub struct TreeCache {
+    slots: Mutex<HashMap<String, TreeSlot>>,
+}
+
+impl TreeCache {
+    /// The tree to search `board` with, reused if it was searched on an earlier position of
+    /// the game, and the number of the search to hand back to [`TreeCache::keep`].
+    pub fn take(&self, key: &str, board: &Board) -> (SearchTree, u64) {
+        let (tree, search) = {
+            let mut slots = self.slots.lock().unwrap();
+            slots.retain(|_, slot| slot.used.elapsed() < MAX_TREE_AGE);
+            if !slots.contains_key(key) && slots.len() >= MAX_TREES {
+                evict_least_recently_used(&mut slots);
+            }
+            let slot = slots.entry(key.to_string()).or_insert_with(|| TreeSlot {
+                used: Instant::now(),
+                searches: 0,
+                tree: None,
+            });
+            slot.searches += 1;
+            (slot.tree.take(), slot.searches)
+        };
+
+        let Some(mut tree) = tree else {
+            return (SearchTree::new(board), search);
+        };
+        if same_position(tree.board(), board) || tree.advance(board) {
+            debug!("Reusing search tree with {} simulations", tree.visits());
+            return (tree, search);
+        }
+        debug!(
+            "Search tree not reused, observed moves {:?}",
+            observed_moves(tree.board(), board)
+        );
+        (SearchTree::new(board), search)
+    }
+
+    /// Stores the tree of `search` for the next turn. A search that was superseded by a later
+    /// one, e.g. pondering on a board that was not reached, must not replace its tree, nor is
+    /// a tree kept for a key that was removed in the meantime.
+    pub fn keep(&self, key: &str, tree: SearchTree, search: u64) {
+        let mut slots = self.slots.lock().unwrap();
+        match slots.get_mut(key) {
+            Some(slot) if slot.searches == search => {
+                slot.used = Instant::now();
+                slot.tree = Some(tree);
+            }
+            _ => debug!("Dropping search tree of a superseded search"),
+        }
+    }
+
+    /// Drops the tree of `key`, e.g. when its game ended
+    pub fn remove(&self, key: &str) {
+        self.slots.lock().unwrap().remove(key);
+    }
+}
+
+fn evict_least_recently_used(slots: &mut HashMap<String, TreeSlot>) {
+    let oldest = slots
+        .iter()
+        .min_by_key(|(_, slot)| slot.used)
+        .map(|(k, _)| k.clone());
+    if let Some(oldest) = oldest {
+        slots.remove(&oldest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Coord;
+    use crate::test_support::{board, snake};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn open_board() -> Board {
+        board(
+            7,
+            7,
+            vec![
+                snake(
+                    "me",
+                    vec![
+                        Coord { x: 1, y: 1 },
+                        Coord { x: 1, y: 0 },
+                        Coord { x: 2, y: 0 },
+                    ],
+                ),
+                snake(
+                    "other",
+                    vec![
+                        Coord { x: 5, y: 5 },
+                        Coord { x: 5, y: 6 },
+                        Coord { x: 6, y: 6 },
+                    ],
+                ),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_avoids_head_to_head_with_longer_snake() {
+        // Moving up meets the longer head at (3,4)
+        let b = board(
+            7,
+            7,
+            vec![
+                snake("me", vec![Coord { x: 3, y: 3 }, Coord { x: 3, y: 2 }]),
+                snake(
+                    "other",
+                    vec![
+                        Coord { x: 3, y: 5 },
+                        Coord { x: 3, y: 6 },
+                        Coord { x: 4, y: 6 },
+                    ],
+                ),
+            ],
+        );
+        let mut tree = SearchTree::new(&b);
+        let mut rng = StdRng::seed_from_u64(1);
+        tree.search(2_000, &Deadline::never(), &mut rng);
+
+        let (dir, _) = tree.best_move("me").unwrap();
+        assert_ne!(dir, Direction::Up);
+        assert_eq!(tree.visits(), 2_000);
+    }
+
+    #[test]
+    fn test_advance_keeps_the_subtree() {
+        let b = open_board();
+        let mut tree = SearchTree::new(&b);
+        let mut rng = StdRng::seed_from_u64(2);
+        tree.search(500, &Deadline::never(), &mut rng);
+
+        // Take a joint move that was searched and play it on the real board
+        let root = &tree.nodes[tree.root];
+        let (indices, child) = root.children.iter().next().unwrap();
+        let moves = root.joint_move(indices);
+        let child_visits = tree.nodes[*child].players[0]
+            .stats
+            .iter()
+            .map(|s| s.visits)
+            .sum::<u32>();
+        let mut next = b.clone();
+        step(&mut next, &moves, &tree.ruleset, &mut rng);
+
+        assert!(tree.advance(&next));
+        assert!(same_position(tree.board(), &next));
+        assert_eq!(tree.visits(), child_visits);
+        assert!(tree.nodes.len() < 500);
+        assert!(tree
+            .nodes
+            .iter()
+            .all(|n| n.children.values().all(|c| *c < tree.nodes.len())));
+    }
+
+    #[test]
+    fn test_advance_fails_on_unknown_board() {
+        let b = open_board();
+        let mut tree = SearchTree::new(&b);
+        tree.search(50, &Deadline::never(), &mut StdRng::seed_from_u64(3));
+
+        let mut spawned = b.clone();
+        spawned.food.push(Coord { x: 3, y: 3 });
+        assert!(!tree.advance(&spawned));
+    }
+
+    #[test]
+    fn test_search_stops_at_deadline() {
+        let mut tree = SearchTree::new(&open_board());
+        let deadline = Deadline::never();
+        deadline.cancel();
+        tree.search(1_000, &deadline, &mut StdRng::seed_from_u64(4));
+        assert_eq!(tree.visits(), 0);
+    }
+
+    #[test]
+    fn test_superseded_search_does_not_replace_the_tree() {
+        let b = open_board();
+        let cache = TreeCache::default();
+        let mut rng = StdRng::seed_from_u64(4);
+
+        // Pondering on a predicted board, then searching the board that was actually reached
+        let (mut pondered, ponder_search) = cache.take("g/me", &b);
+        let (mut actual, actual_search) = cache.take("g/me", &b);
+        actual.search(50, &Deadline::never(), &mut rng);
+        cache.keep("g/me", actual, actual_search);
+        pondered.search(10, &Deadline::never(), &mut rng);
+        cache.keep("g/me", pondered, ponder_search);
+
+        assert_eq!(cache.take("g/me", &b).0.visits(), 50);
+    }
+
+    #[test]
+    fn test_removed_trees_are_not_kept() {
+        let b = open_board();
+        let cache = TreeCache::default();
+        let mut rng = StdRng::seed_from_u64(5);
+
+        let (mut tree, search) = cache.take("g/me", &b);
+        tree.search(50, &Deadline::never(), &mut rng);
+        // The game ends while the search is still running
+        cache.remove("g/me");
+        cache.keep("g/me", tree, search);
+
+        assert!(cache.slots.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_search_stops_at_node_limit() {
+        let mut tree = SearchTree::new(&open_board());
+        tree.max_nodes = 100;
+        let mut rng = StdRng::seed_from_u64(6);
+        tree.search(1_000, &Deadline::never(), &mut rng);
+        assert_eq!(tree.nodes.len(), 100);
+    }
+}
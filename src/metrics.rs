@@ -0,0 +1,328 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// Upper bounds of the latency buckets in seconds, the game timeout is usually 0.5 s
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.4, 0.5, 1.0];
+
+#[derive(Debug)]
+struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<AtomicU64>,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Histogram {
+        Histogram {
+            bounds,
+            counts: bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(i) = self.bounds.iter().position(|b| seconds <= *b) {
+            self.counts[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    // Buckets are cumulative in the exposition format, `count` includes values above the
+    // largest bound
+    fn render(&self, out: &mut String, name: &str, labels: &str, count: u64) {
+        let mut cumulative = 0;
+        for (bound, c) in self.bounds.iter().zip(&self.counts) {
+            cumulative += c.load(Ordering::Relaxed);
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, bound, cumulative
+            );
+        }
+        let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, count);
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, count);
+    }
+}
+
+/// Why a move did not come from the strategy
+#[derive(Debug, Clone, Copy)]
+pub enum Fallback {
+    Timeout,
+    Error,
+    /// The request could not be parsed or describes an impossible board
+    InvalidRequest,
+}
+
+impl Fallback {
+    pub fn label(self) -> &'static str {
+        match self {
+            Fallback::Timeout => "timeout",
+            Fallback::Error => "error",
+            Fallback::InvalidRequest => "invalid-request",
+        }
+    }
+}
+
+/// Counters of the server, exposed in the Prometheus text format on `/metrics`.
+#[derive(Debug)]
+pub struct Metrics {
+    strategy: String,
+    started: Instant,
+    moves: AtomicU64,
+    latency: Histogram,
+    timeouts: AtomicU64,
+    errors: AtomicU64,
+    invalid_requests: AtomicU64,
+    won: AtomicU64,
+    lost: AtomicU64,
+    search_nodes: AtomicU64,
+    // f64 bits of the rate of the last search
+    nodes_per_second: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new(strategy: &str) -> Metrics {
+        Metrics {
+            strategy: strategy.to_string(),
+            started: Instant::now(),
+            moves: AtomicU64::new(0),
+            latency: Histogram::new(LATENCY_BUCKETS),
+            timeouts: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            invalid_requests: AtomicU64::new(0),
+            won: AtomicU64::new(0),
+            lost: AtomicU64::new(0),
+            search_nodes: AtomicU64::new(0),
+            nodes_per_second: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts a served move and the time from receiving the request to answering it
+    pub fn record_move(&self, latency: Duration) {
+        self.latency.observe(latency);
+        self.moves.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_fallback(&self, reason: Fallback) {
+        let counter = match reason {
+            Fallback::Timeout => &self.timeouts,
+            Fallback::Error => &self.errors,
+            Fallback::InvalidRequest => &self.invalid_requests,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts the nodes of a finished search that ran for `elapsed`
+    pub fn record_search(&self, nodes: u64, elapsed: Duration) {
+        self.search_nodes.fetch_add(nodes, Ordering::Relaxed);
+        let rate = nodes as f64 / elapsed.as_secs_f64().max(1e-6);
+        self.nodes_per_second
+            .store(rate.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn game_ended(&self, won: bool) {
+        let counter = if won { &self.won } else { &self.lost };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// The metrics in the Prometheus text format, `active_games` is tracked by the sessions
+    pub fn render(&self, active_games: usize) -> String {
+        let mut out = String::new();
+        let strategy = format!("strategy=\"{}\"", self.strategy);
+        let header = |out: &mut String, name: &str, help: &str, kind: &str| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        };
+
+        header(
+            &mut out,
+            "battlesnake_uptime_seconds",
+            "Time since the server started.",
+            "gauge",
+        );
+        let _ = writeln!(
+            out,
+            "battlesnake_uptime_seconds {}",
+            self.uptime().as_secs_f64()
+        );
+
+        header(
+            &mut out,
+            "battlesnake_moves_total",
+            "Moves served.",
+            "counter",
+        );
+        let moves = self.moves.load(Ordering::Relaxed);
+        let _ = writeln!(out, "battlesnake_moves_total{{{}}} {}", strategy, moves);
+
+        header(
+            &mut out,
+            "battlesnake_move_duration_seconds",
+            "Time from receiving a move request to answering it.",
+            "histogram",
+        );
+        self.latency.render(
+            &mut out,
+            "battlesnake_move_duration_seconds",
+            &strategy,
+            moves,
+        );
+
+        header(
+            &mut out,
+            "battlesnake_fallback_moves_total",
+            "Moves not decided by the strategy.",
+            "counter",
+        );
+        for (reason, c) in [
+            (Fallback::Timeout, &self.timeouts),
+            (Fallback::Error, &self.errors),
+            (Fallback::InvalidRequest, &self.invalid_requests),
+        ] {
+            let _ = writeln!(
+                out,
+                "battlesnake_fallback_moves_total{{{},reason=\"{}\"}} {}",
+                strategy,
+                reason.label(),
+                c.load(Ordering::Relaxed)
+            );
+        }
+
+        header(
+            &mut out,
+            "battlesnake_active_games",
+            "Games started and not ended yet.",
+            "gauge",
+        );
+        let _ = writeln!(out, "battlesnake_active_games {}", active_games);
+
+        header(
+            &mut out,
+            "battlesnake_games_total",
+            "Ended games by result.",
+            "counter",
+        );
+        for (result, c) in [("won", &self.won), ("lost", &self.lost)] {
+            let _ = writeln!(
+                out,
+                "battlesnake_games_total{{{},result=\"{}\"}} {}",
+                strategy,
+                result,
+                c.load(Ordering::Relaxed)
+            );
+        }
+
+        header(
+            &mut out,
+            "battlesnake_search_nodes_total",
+            "Nodes expanded by searching strategies.",
+            "counter",
+        );
+        let _ = writeln!(
+            out,
+            "battlesnake_search_nodes_total{{{}}} {}",
+            strategy,
+            self.search_nodes.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "battlesnake_search_nodes_per_second",
+            "Search speed of the last move.",
+            "gauge",
+        );
+        let rate = f64::from_bits(self.nodes_per_second.load(Ordering::Relaxed));
+        let _ = writeln!(
+            out,
+            "battlesnake_search_nodes_per_second{{{}}} {}",
+            strategy, rate
+        );
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value<'a>(out: &'a str, series: &str) -> Option<&'a str> {
+        out.lines()
+            .find_map(|l| l.strip_prefix(series)?.strip_prefix(' '))
+    }
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let metrics = Metrics::new("simple");
+        for ms in [3, 30, 300, 2_000] {
+            metrics.record_move(Duration::from_millis(ms));
+        }
+        let out = metrics.render(0);
+
+        let bucket = |le: &str| {
+            value(
+                &out,
+                &format!(
+                    "battlesnake_move_duration_seconds_bucket{{strategy=\"simple\",le=\"{}\"}}",
+                    le
+                ),
+            )
+        };
+        assert_eq!(bucket("0.005"), Some("1"));
+        assert_eq!(bucket("0.05"), Some("2"));
+        assert_eq!(bucket("0.4"), Some("3"));
+        assert_eq!(bucket("1"), Some("3"));
+        assert_eq!(bucket("+Inf"), Some("4"));
+        assert_eq!(
+            value(
+                &out,
+                "battlesnake_move_duration_seconds_sum{strategy=\"simple\"}"
+            ),
+            Some("2.333")
+        );
+        assert_eq!(
+            value(&out, "battlesnake_moves_total{strategy=\"simple\"}"),
+            Some("4")
+        );
+    }
+
+    #[test]
+    fn test_games_and_fallbacks() {
+        let metrics = Metrics::new("duel");
+        metrics.game_ended(true);
+        metrics.record_fallback(Fallback::Timeout);
+        metrics.record_search(500, Duration::from_millis(250));
+        let out = metrics.render(1);
+
+        assert_eq!(value(&out, "battlesnake_active_games"), Some("1"));
+        assert_eq!(
+            value(
+                &out,
+                "battlesnake_games_total{strategy=\"duel\",result=\"won\"}"
+            ),
+            Some("1")
+        );
+        assert_eq!(
+            value(
+                &out,
+                "battlesnake_fallback_moves_total{strategy=\"duel\",reason=\"timeout\"}"
+            ),
+            Some("1")
+        );
+        assert_eq!(
+            value(
+                &out,
+                "battlesnake_search_nodes_per_second{strategy=\"duel\"}"
+            ),
+            Some("2000")
+        );
+    }
+}
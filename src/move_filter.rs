@@ -0,0 +1,349 @@
+use crate::game::{all_directions, Battlesnake, Board, Coord, Direction, Game};
+use crate::rules::Ruleset;
+use crate::safe_move::{is_occupied_next_turn, move_safety, step};
+
+/// The snake whose moves are filtered and how moves across the edge behave.
+pub struct MoveContext<'a> {
+    pub board: &'a Board,
+    pub snake: &'a Battlesnake,
+    /// Moves across an edge come back in on the opposite side
+    pub wrap: bool,
+}
+
+impl MoveContext<'_> {
+    pub fn next(&self, from: Coord, dir: Direction) -> Coord {
+        step(self.board, from, dir, self.wrap)
+    }
+
+    /// Cell our head moves to
+    pub fn target(&self, dir: Direction) -> Coord {
+        self.next(self.snake.head, dir)
+    }
+}
+
+pub trait MoveFilter {
+    fn allows(&self, ctx: &MoveContext, dir: Direction) -> bool;
+}
+
+/// Rules out moves off the board. Boards that wrap have no walls.
+pub struct Walls;
+
+impl MoveFilter for Walls {
+    fn allows(&self, ctx: &MoveContext, dir: Direction) -> bool {
+        let c = ctx.target(dir);
+        c.x >= 0 && c.y >= 0 && c.x < ctx.board.width && c.y < ctx.board.height
+    }
+}
+
+/// Rules out moves into the body of any snake, except tails that surely move away this turn.
+pub struct Bodies;
+
+impl MoveFilter for Bodies {
+    fn allows(&self, ctx: &MoveContext, dir: Direction) -> bool {
+        !is_occupied_next_turn(ctx.board, ctx.snake, ctx.wrap, &ctx.target(dir))
+    }
+}
+
+/// Rules out entering a hazard without food if its damage would eliminate us.
+pub struct Hazards {
+    pub damage: i32,
+}
+
+impl Hazards {
+    /// Hazard damage from the settings of the game's ruleset, the standard damage if the game
+    /// does not say
+    pub fn for_game(game: &Game) -> Hazards {
+        let damage = game
+            .ruleset
+            .get("settings")
+            .and_then(|s| s.get("hazardDamagePerTurn"))
+            .and_then(|d| d.as_i64())
+            .map_or(Ruleset::default().hazard_damage_per_turn, |d| d as i32);
+        Hazards { damage }
+    }
+}
+
+impl MoveFilter for Hazards {
+    fn allows(&self, ctx: &MoveContext, dir: Direction) -> bool {
+        let c = ctx.target(dir);
+        let lethal = ctx.board.hazards.contains(&c)
+            && !ctx.board.food.contains(&c)
+            && ctx.snake.health - 1 - self.damage <= 0;
+        !lethal
+    }
+}
+
+/// Rules out cells an opponent at least as long as we are can move onto as well.
+pub struct HeadToHead;
+
+impl MoveFilter for HeadToHead {
+    fn allows(&self, ctx: &MoveContext, dir: Direction) -> bool {
+        let c = ctx.target(dir);
+        !ctx.board
+            .snakes
+            .iter()
+            .filter(|s| s.id != ctx.snake.id && s.body.len() >= ctx.snake.body.len())
+            .any(|s| {
+                all_directions()
+                    .into_iter()
+                    .any(|d| ctx.next(s.head, d) == c)
+            })
+    }
+}
+
+/// Whether the game is played on a board whose edges wrap around
+pub fn is_wrapped(game: &Game) -> bool {
+    game.ruleset.get("name").and_then(|n| n.as_str()) == Some("wrapped")
+}
+
+/// Filters applied one after the other to the four moves of a snake.
+#[derive(Default)]
+pub struct MoveFilters {
+    wrap: bool,
+    filters: Vec<Box<dyn MoveFilter + Send + Sync>>,
+}
+
+impl MoveFilters {
+    pub fn new() -> MoveFilters {
+        MoveFilters::default()
+    }
+
+    /// Walls and bodies, the moves that are not lost right away. Searches explore these.
+    pub fn legal() -> MoveFilters {
+        MoveFilters::new().with(Walls).with(Bodies)
+    }
+
+    /// Walls, bodies, lethal hazards and head-to-head under the rules of `game`, from hard to
+    /// soft constraints
+    pub fn standard(game: &Game) -> MoveFilters {
+        MoveFilters::legal()
+            .wrapped(is_wrapped(game))
+            .with(Hazards::for_game(game))
+            .with(HeadToHead)
+    }
+
+    pub fn wrapped(mut self, wrap: bool) -> MoveFilters {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn with<F: MoveFilter + Send + Sync + 'static>(mut self, filter: F) -> MoveFilters {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    fn context<'a>(&self, board: &'a Board, snake: &'a Battlesnake) -> MoveContext<'a> {
+        MoveContext {
+            board,
+            snake,
+            wrap: self.wrap,
+        }
+    }
+
+    /// Moves that pass every filter, possibly none
+    pub fn allowed(&self, board: &Board, snake: &Battlesnake) -> Vec<Direction> {
+        let ctx = self.context(board, snake);
+        all_directions()
+            .into_iter()
+            .filter(|d| self.filters.iter().all(|f| f.allows(&ctx, *d)))
+            .collect()
+    }
+
+    /// Like `allowed`, but a filter that would rule out every remaining move is skipped, so
+    /// the earlier filters decide when there is no move satisfying all of them. Of the moves
+    /// left after skipping a filter only the safest by `move_safety` are kept. Never empty.
+    pub fn preferred(&self, board: &Board, snake: &Battlesnake) -> Vec<Direction> {
+        let ctx = self.context(board, snake);
+        let mut moves = all_directions();
+        let mut skipped = false;
+        for filter in &self.filters {
+            let kept = moves
+                .iter()
+                .copied()
+                .filter(|d| filter.allows(&ctx, *d))
+                .collect::<Vec<_>>();
+            if kept.is_empty() {
+                skipped = true;
+            } else {
+                moves = kept;
+            }
+        }
+        if skipped {
+            let safety = move_safety(board, snake, self.wrap);
+            let rank = |d: &Direction| {
+                let (_, s) = safety.iter().find(|(m, _)| m == d).unwrap();
+                s.rank()
+            };
+            let best = moves.iter().map(rank).max();
+            moves.retain(|d| Some(rank(d)) == best);
+        }
+        moves
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{board, snake};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn game(name: &str) -> Game {
+        Game {
+            id: "g".to_string(),
+            ruleset: HashMap::from([("name".to_string(), name.into())]),
+            timeout: 500,
+        }
+    }
+
+    fn corner_snake() -> Battlesnake {
+        snake(
+            "me",
+            vec![
+                Coord { x: 0, y: 0 },
+                Coord { x: 0, y: 1 },
+                Coord { x: 0, y: 2 },
+            ],
+        )
+    }
+
+    #[test]
+    fn test_walls() {
+        let me = corner_snake();
+        let b = board(5, 5, vec![me.clone()]);
+        let walls = MoveFilters::new().with(Walls);
+        assert_eq!(
+            walls.allowed(&b, &me),
+            vec![Direction::Right, Direction::Up]
+        );
+        assert_eq!(walls.wrapped(true).allowed(&b, &me), all_directions());
+    }
+
+    #[test]
+    fn test_bodies_with_vacating_tail() {
+        let me = snake(
+            "me",
+            vec![
+                Coord { x: 1, y: 1 },
+                Coord { x: 1, y: 2 },
+                Coord { x: 2, y: 2 },
+                Coord { x: 2, y: 1 },
+            ],
+        );
+        let other = snake("other", vec![Coord { x: 0, y: 2 }, Coord { x: 0, y: 1 }]);
+        let b = board(5, 5, vec![me.clone(), other.clone()]);
+        let bodies = MoveFilters::new().with(Bodies);
+        // The tail of the other snake at (0,1) moves away, so does ours at (2,1)
+        assert_eq!(bodies.allowed(&b, &me).len(), 3);
+
+        let mut fed = other;
+        fed.body.push(Coord { x: 0, y: 1 });
+        let b = board(5, 5, vec![me.clone(), fed]);
+        assert_eq!(
+            bodies.allowed(&b, &me),
+            vec![Direction::Right, Direction::Down]
+        );
+    }
+
+    #[test]
+    fn test_lethal_hazards() {
+        let mut me = snake("me", vec![Coord { x: 2, y: 2 }, Coord { x: 2, y: 1 }]);
+        let mut b = board(5, 5, vec![me.clone()]);
+        b.hazards = vec![Coord { x: 1, y: 2 }, Coord { x: 3, y: 2 }];
+        b.food = vec![Coord { x: 3, y: 2 }];
+        let hazards = MoveFilters::new().with(Hazards { damage: 14 });
+        assert_eq!(hazards.allowed(&b, &me).len(), 4);
+
+        me.health = 15;
+        assert_eq!(
+            hazards.allowed(&b, &me),
+            vec![Direction::Right, Direction::Up, Direction::Down]
+        );
+    }
+
+    #[test]
+    fn test_head_to_head() {
+        let me = snake("me", vec![Coord { x: 2, y: 2 }, Coord { x: 2, y: 1 }]);
+        let other = snake(
+            "other",
+            vec![
+                Coord { x: 1, y: 3 },
+                Coord { x: 0, y: 3 },
+                Coord { x: 0, y: 4 },
+            ],
+        );
+        let b = board(5, 5, vec![me.clone(), other]);
+        let head_to_head = MoveFilters::new().with(HeadToHead);
+        assert_eq!(
+            head_to_head.allowed(&b, &me),
+            vec![Direction::Right, Direction::Down]
+        );
+
+        // Shorter snakes lose the collision, they are no danger
+        let short = snake("other", vec![Coord { x: 1, y: 3 }]);
+        let b = board(5, 5, vec![me.clone(), short]);
+        assert_eq!(head_to_head.allowed(&b, &me).len(), 4);
+    }
+
+    #[test]
+    fn test_preferred_skips_filters_that_leave_nothing() {
+        // In the corner, the only move off the wall meets a longer head
+        let me = corner_snake();
+        let other = snake(
+            "other",
+            vec![
+                Coord { x: 2, y: 0 },
+                Coord { x: 3, y: 0 },
+                Coord { x: 4, y: 0 },
+            ],
+        );
+        let b = board(5, 5, vec![me.clone(), other]);
+        let filters = MoveFilters::standard(&game("standard"));
+        assert!(filters.allowed(&b, &me).is_empty());
+        assert_eq!(filters.preferred(&b, &me), vec![Direction::Right]);
+    }
+
+    #[test]
+    fn test_preferred_ranks_moves_when_bodies_leave_nothing() {
+        // We just ate in the corner, both moves off the wall run into a body
+        let mut me = snake(
+            "me",
+            vec![
+                Coord { x: 0, y: 0 },
+                Coord { x: 0, y: 1 },
+                Coord { x: 1, y: 1 },
+                Coord { x: 1, y: 0 },
+            ],
+        );
+        me.body.push(Coord { x: 1, y: 0 });
+        // Above us there is no room at all
+        let other = snake(
+            "other",
+            vec![
+                Coord { x: 0, y: 2 },
+                Coord { x: 0, y: 3 },
+                Coord { x: 0, y: 4 },
+            ],
+        );
+        let b = board(5, 5, vec![me.clone(), other]);
+        let filters = MoveFilters::standard(&game("standard"));
+        assert!(filters.allowed(&b, &me).is_empty());
+        assert_eq!(filters.preferred(&b, &me), vec![Direction::Right]);
+    }
+
+    #[test]
+    fn test_hazard_damage_from_ruleset() {
+        let mut g = game("standard");
+        assert_eq!(Hazards::for_game(&g).damage, 14);
+        g.ruleset
+            .insert("settings".to_string(), json!({"hazardDamagePerTurn": 50}));
+        assert_eq!(Hazards::for_game(&g).damage, 50);
+    }
+
+    #[test]
+    fn test_wrapped_ruleset() {
+        assert!(is_wrapped(&game("wrapped")));
+        assert!(!is_wrapped(&game("standard")));
+        assert!(MoveFilters::standard(&game("wrapped")).wrap);
+    }
+}
@@ -0,0 +1,80 @@
+use crate::game::{get_direction_from_to, Board, Direction};
+use std::collections::HashMap;
+
+/// Moves the snakes made between two consecutive boards, inferred from their heads. Snakes
+/// that were eliminated or whose head did not move by a single cell are left out.
+pub fn observed_moves(prev: &Board, next: &Board) -> HashMap<String, Direction> {
+    prev.snakes
+        .iter()
+        .filter_map(|s| {
+            let moved = next.snakes.iter().find(|n| n.id == s.id)?;
+            let head = moved.body.first()?;
+            let dir = get_direction_from_to(s.body.first()?, head)?;
+            Some((s.id.clone(), dir))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Coord;
+    use crate::rules::{step, Ruleset};
+    use crate::test_support::{board, snake};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_recovers_the_joint_move() {
+        let prev = board(
+            7,
+            7,
+            vec![
+                snake("a", vec![Coord { x: 1, y: 1 }, Coord { x: 1, y: 0 }]),
+                snake("b", vec![Coord { x: 5, y: 5 }, Coord { x: 5, y: 6 }]),
+            ],
+        );
+        let moves = HashMap::from([
+            ("a".to_string(), Direction::Right),
+            ("b".to_string(), Direction::Down),
+        ]);
+        let mut next = prev.clone();
+        step(
+            &mut next,
+            &moves,
+            &Ruleset::default(),
+            &mut StdRng::seed_from_u64(0),
+        );
+
+        assert_eq!(observed_moves(&prev, &next), moves);
+    }
+
+    #[test]
+    fn test_eliminated_snakes_are_left_out() {
+        let prev = board(
+            7,
+            7,
+            vec![
+                snake("a", vec![Coord { x: 1, y: 1 }, Coord { x: 1, y: 0 }]),
+                snake("b", vec![Coord { x: 0, y: 5 }, Coord { x: 1, y: 5 }]),
+            ],
+        );
+        let next = board(
+            7,
+            7,
+            vec![snake("a", vec![Coord { x: 1, y: 2 }, Coord { x: 1, y: 1 }])],
+        );
+
+        assert_eq!(
+            observed_moves(&prev, &next),
+            HashMap::from([("a".to_string(), Direction::Up)])
+        );
+    }
+
+    #[test]
+    fn test_jumps_are_not_moves() {
+        let prev = board(7, 7, vec![snake("a", vec![Coord { x: 1, y: 1 }])]);
+        let next = board(7, 7, vec![snake("a", vec![Coord { x: 3, y: 1 }])]);
+        assert!(observed_moves(&prev, &next).is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+use crate::game::Game;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Share of the game timeout a search may use, the rest covers network latency
+const SEARCH_SHARE_OF_TIMEOUT: f64 = 0.5;
+
+/// Point in time a search has to be finished by. It can also be cancelled early,
+/// the cancellation is shared by all clones, as is the count of searched nodes.
+#[derive(Debug, Clone)]
+pub struct Deadline {
+    end: Option<Instant>,
+    cancelled: Arc<AtomicBool>,
+    nodes: Arc<AtomicU64>,
+}
+
+impl Deadline {
+    pub fn after(duration: Duration) -> Deadline {
+        Deadline {
+            end: Some(Instant::now() + duration),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            nodes: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Deadline that only ends when cancelled
+    pub fn never() -> Deadline {
+        Deadline {
+            end: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            nodes: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Deadline for deciding a move within the timeout of `game`
+    pub fn for_turn(game: &Game) -> Deadline {
+        Deadline::after(search_time(game))
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn expired(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.end.is_some_and(|e| Instant::now() >= e)
+    }
+
+    /// Counts nodes searched before this deadline, for reporting only
+    pub fn add_nodes(&self, nodes: u64) {
+        self.nodes.fetch_add(nodes, Ordering::Relaxed);
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.nodes.load(Ordering::Relaxed)
+    }
+}
+
+/// Time a search may take for a move within the timeout of `game`
+pub fn search_time(game: &Game) -> Duration {
+    Duration::from_millis((game.timeout as f64 * SEARCH_SHARE_OF_TIMEOUT) as u64)
+}
+
+pub fn worker_count() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// Started on the first search and kept for the lifetime of the server
+fn pool() -> &'static ThreadPool {
+    static POOL: OnceLock<ThreadPool> = OnceLock::new();
+    POOL.get_or_init(|| {
+        ThreadPoolBuilder::new()
+            .num_threads(worker_count())
+            .thread_name(|i| format!("search-{}", i))
+            .build()
+            .expect("search thread pool")
+    })
+}
+
+/// Parallel search: runs `search` for every root on a persistent pool with one worker thread
+/// per CPU core and returns the results in the order of `roots`. Roots can be anything that
+/// splits the work, e.g. pairs of moves below the root. Each search is expected to return its
+/// best result so far once `deadline` has expired.
+pub fn search_roots<T, R, F>(roots: &[T], deadline: &Deadline, search: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T, &Deadline) -> R + Sync,
+{
+    pool().install(|| roots.par_iter().map(|r| search(r, deadline)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_results_keep_root_order() {
+        let roots = (0..20).collect::<Vec<u64>>();
+        let results = search_roots(&roots, &Deadline::never(), |r, _| r * r);
+        assert_eq!(results, roots.iter().map(|r| r * r).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_searches_run_on_the_pool() {
+        for _ in 0..2 {
+            let names = search_roots(&[0, 1], &Deadline::never(), |_, _| {
+                thread::current().name().map(String::from)
+            });
+            assert!(names
+                .iter()
+                .all(|n| n.as_deref().is_some_and(|n| n.starts_with("search-"))));
+        }
+    }
+
+    #[test]
+    fn test_searches_stop_at_deadline() {
+        let deadline = Deadline::after(Duration::from_millis(20));
+        let start = Instant::now();
+        let results = search_roots(&[1, 2, 3], &deadline, |_, deadline| {
+            let mut iterations = 0u64;
+            while !deadline.expired() {
+                iterations += 1;
+                thread::sleep(Duration::from_millis(1));
+            }
+            iterations
+        });
+
+        assert_eq!(results.len(), 3);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_cancel_is_shared_by_clones() {
+        let deadline = Deadline::never();
+        let clone = deadline.clone();
+        assert!(!clone.expired());
+        deadline.cancel();
+        assert!(clone.expired());
+
+        clone.add_nodes(3);
+        deadline.add_nodes(4);
+        assert_eq!(deadline.nodes(), 7);
+    }
+}
@@ -1,4 +1,6 @@
-use crate::game::{get_direction_from_to, Coord, Direction};
+use crate::game::{get_direction_from_to, Battlesnake, Board, Coord, Direction};
+use crate::grid::Grid;
+use crate::safe_move::is_occupied_next_turn;
 use std::cmp::Ordering;
 use std::collections::{BinaryHeap, HashMap};
 use std::fmt::Debug;
@@ -9,19 +11,34 @@ struct DijkQueueItem {
     position: Coord,
 }
 
+/// Distances (`u32::MAX` if not reached) and predecessors of a search from `start`.
 #[derive(Debug, Clone)]
 pub struct DijkResult {
     start: Coord,
-    distances: HashMap<Coord, u32>,
-    predecessors: HashMap<Coord, Coord>,
+    distances: Grid<u32>,
+    predecessors: Grid<Option<Coord>>,
 }
 
 impl DijkResult {
-    pub fn get_distance_ref(&self) -> &HashMap<Coord, u32> {
+    fn new(start: Coord, board_size: (i32, i32)) -> DijkResult {
+        let mut distances = Grid::new(board_size.0, board_size.1, u32::MAX);
+        if let Some(d) = distances.get_mut(&start) {
+            *d = 0;
+        }
+        DijkResult {
+            start,
+            distances,
+            predecessors: Grid::new(board_size.0, board_size.1, None),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_distance_ref(&self) -> &Grid<u32> {
         &self.distances
     }
 
-    pub fn get_predecessor_ref(&self) -> &HashMap<Coord, Coord> {
+    #[allow(dead_code)]
+    pub fn get_predecessor_ref(&self) -> &Grid<Option<Coord>> {
         &self.predecessors
     }
 
@@ -31,7 +48,7 @@ impl DijkResult {
             let mut path = Vec::new();
             path.push(coord);
             let mut current = coord;
-            while let Some(&predecessor) = self.predecessors.get(&current) {
+            while let Some(&Some(predecessor)) = self.predecessors.get(&current) {
                 path.push(predecessor);
                 current = predecessor;
             }
@@ -64,6 +81,19 @@ impl DijkResult {
 
         get_direction_from_to(&self.start, first_step)
     }
+
+    // Relaxes the edge from `from` to `to`, returns the new distance of `to` if it improved
+    fn relax(&mut self, from: Coord, to: Coord) -> Option<u32> {
+        let alt_dist = self.distances[from] + 1;
+        let dist = &mut self.distances[to];
+        if alt_dist < *dist {
+            *dist = alt_dist;
+            self.predecessors[to] = Some(from);
+            Some(alt_dist)
+        } else {
+            None
+        }
+    }
 }
 
 impl PartialOrd for DijkQueueItem {
@@ -78,60 +108,135 @@ impl Ord for DijkQueueItem {
     }
 }
 
+fn manhatten_distance_to(pos: Coord, target: &Coord) -> u32 {
+    ((pos.x - target.x).abs() + (pos.y - target.y).abs()) as u32
+}
+
+#[allow(dead_code)]
 fn manhatten_distance(pos: Coord, targets: Vec<Coord>) -> Vec<u32> {
     targets
         .iter()
-        .map(|target| ((pos.x - target.x).abs() + (pos.y - target.y).abs()) as u32)
+        .map(|target| manhatten_distance_to(pos, target))
         .collect()
 }
 
 pub fn dijkstra(start: Coord, board_size: (i32, i32), blocked_pos: &[Coord]) -> DijkResult {
+    let mut result = DijkResult::new(start, board_size);
+    if !result.distances.contains(&start) {
+        return result;
+    }
+    let blocked = Grid::with_cells(board_size.0, board_size.1, false, blocked_pos, true);
     let mut unvisited = BinaryHeap::new();
-    let mut distances = HashMap::with_capacity(board_size.0 as usize * board_size.1 as usize);
-    let mut predecessors =
-        HashMap::<Coord, Coord>::with_capacity(board_size.0 as usize * board_size.1 as usize);
 
-    distances.insert(start, 0);
     unvisited.push(DijkQueueItem {
         estimated_cost: 0,
         position: start,
     });
 
-    while !unvisited.is_empty() {
-        let current = unvisited.pop().unwrap();
-
-        if blocked_pos.contains(&current.position) && current.position != start {
+    while let Some(current) = unvisited.pop() {
+        // Stale entry, the cell was reached on a shorter path in the meantime
+        if current.estimated_cost > result.distances[current.position] {
+            continue;
+        }
+        if blocked[current.position] && current.position != start {
             continue;
         }
 
-        current
-            .position
-            .neighbors(board_size)
+        for neighbor in current.position.neighbors(board_size) {
+            if let Some(alt_dist) = result.relax(current.position, neighbor) {
+                unvisited.push(DijkQueueItem {
+                    position: neighbor,
+                    estimated_cost: alt_dist,
+                });
+            }
+        }
+    }
+
+    result
+}
+
+/// A* search from `start` to the nearest of `goals`, using the Manhattan distance to the
+/// closest goal as heuristic. The search stops as soon as a goal is reached, so only the
+/// distances and predecessors on the way to it are complete.
+pub fn astar(
+    start: Coord,
+    board_size: (i32, i32),
+    blocked_pos: &[Coord],
+    goals: &[Coord],
+) -> DijkResult {
+    let mut result = DijkResult::new(start, board_size);
+    if goals.is_empty() || !result.distances.contains(&start) {
+        return result;
+    }
+    let blocked = Grid::with_cells(board_size.0, board_size.1, false, blocked_pos, true);
+    let mut visited = Grid::new(board_size.0, board_size.1, false);
+    let mut unvisited = BinaryHeap::new();
+    let heuristic = |pos: Coord| {
+        goals
             .iter()
-            .for_each(|neighbor| {
-                let current_dist = *distances.get(neighbor).unwrap_or(&u32::MAX);
-                let alt_dist = *distances.get(&current.position).unwrap() + 1;
-                if alt_dist < current_dist {
-                    unvisited.push(DijkQueueItem {
-                        position: *neighbor,
-                        estimated_cost: alt_dist,
-                    });
-                    distances.insert(*neighbor, alt_dist);
-                    predecessors.insert(*neighbor, current.position);
-                }
-            })
+            .map(|goal| manhatten_distance_to(pos, goal))
+            .min()
+            .unwrap_or(0)
+    };
+
+    unvisited.push(DijkQueueItem {
+        estimated_cost: heuristic(start),
+        position: start,
+    });
+
+    while let Some(current) = unvisited.pop() {
+        if std::mem::replace(&mut visited[current.position], true) {
+            continue;
+        }
+        if goals.contains(&current.position) {
+            break;
+        }
+        if blocked[current.position] && current.position != start {
+            continue;
+        }
+
+        for neighbor in current.position.neighbors(board_size) {
+            if let Some(alt_dist) = result.relax(current.position, neighbor) {
+                unvisited.push(DijkQueueItem {
+                    position: neighbor,
+                    estimated_cost: alt_dist + heuristic(neighbor),
+                });
+            }
+        }
     }
 
-    DijkResult {
-        start,
-        distances,
-        predecessors,
+    result
+}
+
+/// First step on the shortest path from the head to our own tail, to circle safely in open
+/// space. The tail cell is free once we move, unless we just ate and it stays put for a turn.
+pub fn direction_to_own_tail(board: &Board, snake: &Battlesnake) -> Option<Direction> {
+    let tail = *snake.body.last()?;
+    let tail_stays = is_occupied_next_turn(board, snake, false, &tail);
+
+    let blocked_pos = board
+        .snakes
+        .iter()
+        .flat_map(|s| s.body.iter().copied())
+        .filter(|c| *c != tail)
+        .collect::<Vec<Coord>>();
+    let result = astar(
+        snake.head,
+        (board.width, board.height),
+        &blocked_pos,
+        &[tail],
+    );
+
+    if tail_stays && result.distances.get(&tail) == Some(&1) {
+        return None;
     }
+    result.get_direction_for_shortest_goal(&[tail])
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::{board, snake};
 
     #[test]
     fn test_single_target() {
@@ -391,4 +496,197 @@ mod tests {
             .unwrap();
         assert_eq!(s_path_dir, Direction::Right);
     }
+
+    #[test]
+    fn test_astar_matches_dijkstra() {
+        let board_size = (6, 6);
+        let blocked_pos = vec![
+            Coord { x: 1, y: 4 },
+            Coord { x: 1, y: 3 },
+            Coord { x: 1, y: 2 },
+            Coord { x: 2, y: 2 },
+            Coord { x: 3, y: 2 },
+            Coord { x: 4, y: 2 },
+            Coord { x: 5, y: 2 },
+        ];
+        let start = Coord { x: 4, y: 3 };
+        let full = dijkstra(start, board_size, &blocked_pos);
+
+        for x in 0..6 {
+            for y in 0..6 {
+                let goal = Coord { x, y };
+                let astar_dist = *astar(start, board_size, &blocked_pos, &[goal])
+                    .get_distance_ref()
+                    .get(&goal)
+                    .unwrap_or(&u32::MAX);
+                let dijkstra_dist = *full.get_distance_ref().get(&goal).unwrap_or(&u32::MAX);
+                assert_eq!(astar_dist, dijkstra_dist, "{:?}", goal);
+            }
+        }
+    }
+
+    #[test]
+    fn test_astar_stops_at_nearest_goal() {
+        let start = Coord { x: 0, y: 0 };
+        let near = Coord { x: 2, y: 0 };
+        let far = Coord { x: 9, y: 9 };
+        let result = astar(start, (10, 10), &[], &[far, near]);
+
+        assert_eq!(result.get_distance_ref().get(&near), Some(&2));
+        assert_eq!(
+            result.retrieve_distances_for(&[far]).next().unwrap().1,
+            &u32::MAX
+        );
+        // Only the cells around the straight line to the goal are explored
+        let reached = result
+            .get_distance_ref()
+            .iter()
+            .filter(|(_, d)| **d != u32::MAX)
+            .count();
+        assert!(reached < 10);
+        assert_eq!(
+            result.get_direction_for_shortest_goal(&[far, near]),
+            Some(Direction::Right)
+        );
+    }
+
+    #[test]
+    fn test_astar_unreachable_and_empty_goals() {
+        let start = Coord { x: 0, y: 0 };
+        let goal = Coord { x: 3, y: 3 };
+        let blocked_pos = (0..5).map(|i| Coord { x: i, y: 2 }).collect::<Vec<_>>();
+
+        let result = astar(start, (5, 5), &blocked_pos, &[goal]);
+        assert_eq!(result.get_direction_for_shortest_goal(&[goal]), None);
+
+        let result = astar(start, (5, 5), &[], &[]);
+        let reached = result
+            .get_distance_ref()
+            .iter()
+            .filter(|(_, d)| **d != u32::MAX)
+            .count();
+        assert_eq!(reached, 1);
+    }
+
+    // The previous HashMap based implementation, kept as reference for the benchmark
+    fn dijkstra_hashmap(
+        start: Coord,
+        board_size: (i32, i32),
+        blocked_pos: &[Coord],
+    ) -> HashMap<Coord, u32> {
+        let mut unvisited = BinaryHeap::new();
+        let mut distances = HashMap::new();
+        distances.insert(start, 0);
+        unvisited.push(DijkQueueItem {
+            estimated_cost: 0,
+            position: start,
+        });
+
+        while let Some(current) = unvisited.pop() {
+            if blocked_pos.contains(&current.position) && current.position != start {
+                continue;
+            }
+            let neighbors = current.position.neighbors(board_size).collect::<Vec<_>>();
+            for neighbor in neighbors {
+                let current_dist = *distances.get(&neighbor).unwrap_or(&u32::MAX);
+                let alt_dist = distances[&current.position] + 1;
+                if alt_dist < current_dist {
+                    unvisited.push(DijkQueueItem {
+                        position: neighbor,
+                        estimated_cost: alt_dist,
+                    });
+                    distances.insert(neighbor, alt_dist);
+                }
+            }
+        }
+        distances
+    }
+
+    /// Run with `cargo test --release bench_grid_dijkstra -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_grid_dijkstra() {
+        use std::time::Instant;
+
+        let board_size = (60, 60);
+        // Walls with alternating gaps, so the search has to wind through the board
+        let blocked_pos = (2..60)
+            .step_by(4)
+            .flat_map(|x| {
+                let gap = if x % 8 == 2 { 0 } else { 59 };
+                (0..60)
+                    .filter(move |y| *y != gap)
+                    .map(move |y| Coord { x, y })
+            })
+            .collect::<Vec<_>>();
+        let start = Coord { x: 0, y: 0 };
+        let runs = 20;
+
+        let t = Instant::now();
+        let mut reference = HashMap::new();
+        for _ in 0..runs {
+            reference = dijkstra_hashmap(start, board_size, &blocked_pos);
+        }
+        let hashmap_time = t.elapsed() / runs;
+
+        let t = Instant::now();
+        let mut result = dijkstra(start, board_size, &blocked_pos);
+        for _ in 1..runs {
+            result = dijkstra(start, board_size, &blocked_pos);
+        }
+        let grid_time = t.elapsed() / runs;
+
+        for (c, d) in result.get_distance_ref().iter() {
+            assert_eq!(reference.get(&c).unwrap_or(&u32::MAX), d, "{:?}", c);
+        }
+        println!(
+            "60x60 dijkstra: hashmap {:?}, grid {:?}, speedup {:.1}x",
+            hashmap_time,
+            grid_time,
+            hashmap_time.as_secs_f64() / grid_time.as_secs_f64()
+        );
+    }
+
+    #[test]
+    fn test_direction_to_own_tail() {
+        // Head at (2,2), body bends down and left, the tail is at (1,3)
+        let me = snake(
+            "me",
+            vec![
+                Coord { x: 2, y: 2 },
+                Coord { x: 2, y: 1 },
+                Coord { x: 1, y: 1 },
+                Coord { x: 1, y: 2 },
+                Coord { x: 1, y: 3 },
+            ],
+        );
+        assert_eq!(
+            direction_to_own_tail(&board(5, 5, vec![me.clone()]), &me),
+            Some(Direction::Up)
+        );
+    }
+
+    #[test]
+    fn test_tail_stays_after_eating() {
+        let body = vec![
+            Coord { x: 1, y: 1 },
+            Coord { x: 2, y: 1 },
+            Coord { x: 2, y: 2 },
+            Coord { x: 1, y: 2 },
+        ];
+        let me = snake("me", body.clone());
+        assert_eq!(
+            direction_to_own_tail(&board(5, 5, vec![me.clone()]), &me),
+            Some(Direction::Up)
+        );
+
+        // Having just eaten the tail does not move, stepping onto it would be a collision
+        let mut grown = body;
+        grown.push(Coord { x: 1, y: 2 });
+        let me = snake("me", grown);
+        assert_eq!(
+            direction_to_own_tail(&board(5, 5, vec![me.clone()]), &me),
+            None
+        );
+    }
 }
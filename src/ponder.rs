@@ -0,0 +1,321 @@
+use crate::game::{Battlesnake, Board, Direction, Game, Move};
+use crate::parallel::Deadline;
+use crate::rules::{step, Ruleset};
+use crate::seeding::move_rng;
+use crate::strategy::{SimpleStrategy, Strategy};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rocket::tokio::task::JoinHandle;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Search started on the board we expect for the next turn, while the opponents are still
+/// thinking about the current one.
+pub struct Prediction {
+    /// Board we expect, set by the search once it predicted the opponents' replies
+    pub board: Arc<OnceLock<Board>>,
+    pub deadline: Deadline,
+    pub started: Instant,
+    /// `None` if we are not expected to survive the turn
+    pub search: JoinHandle<Option<Move>>,
+}
+
+impl Prediction {
+    /// Whether the search runs on `board`
+    pub fn matches(&self, board: &Board) -> bool {
+        self.board.get().is_some_and(|b| same_position(b, board))
+    }
+}
+
+// A game counts as running until no move arrived for this many game timeouts, in case its
+// end was never received
+const ACTIVE_TIMEOUTS: u32 = 10;
+
+/// The games we currently play in, with their running prediction.
+#[derive(Default)]
+pub struct Sessions {
+    /// Point in time each game is considered over without a further move
+    active: Mutex<BTreeMap<(String, String), Instant>>,
+    predictions: Mutex<HashMap<String, Prediction>>,
+}
+
+fn session_key(game_id: &str, snake_id: &str) -> String {
+    format!("{}/{}", game_id, snake_id)
+}
+
+impl Sessions {
+    /// Marks the game as running, also for games that started before a restart
+    pub fn start(&self, game: &Game, snake: &Battlesnake) {
+        self.expire();
+        let expires = Instant::now() + Duration::from_millis(game.timeout.into()) * ACTIVE_TIMEOUTS;
+        self.active
+            .lock()
+            .unwrap()
+            .insert((game.id.clone(), snake.id.clone()), expires);
+    }
+
+    // Drops games without moves for a while together with their prediction
+    fn expire(&self) {
+        let now = Instant::now();
+        let mut active = self.active.lock().unwrap();
+        let mut predictions = self.predictions.lock().unwrap();
+        active.retain(|(game, snake), expires| {
+            let running = *expires > now;
+            if !running {
+                if let Some(p) = predictions.remove(&session_key(game, snake)) {
+                    p.deadline.cancel();
+                }
+            }
+            running
+        });
+    }
+
+    /// Ids of the running games
+    pub fn active_games(&self) -> Vec<String> {
+        self.expire();
+        let mut ids = self
+            .active
+            .lock()
+            .unwrap()
+            .keys()
+            .map(|(game, _)| game.clone())
+            .collect::<Vec<_>>();
+        ids.dedup();
+        ids
+    }
+
+    pub fn take(&self, game: &Game, snake: &Battlesnake) -> Option<Prediction> {
+        self.expire();
+        self.predictions
+            .lock()
+            .unwrap()
+            .remove(&session_key(&game.id, &snake.id))
+    }
+
+    /// Stores `prediction`, a previous one of the same game is cancelled
+    pub fn insert(&self, game: &Game, snake: &Battlesnake, prediction: Prediction) {
+        let previous = self
+            .predictions
+            .lock()
+            .unwrap()
+            .insert(session_key(&game.id, &snake.id), prediction);
+        if let Some(p) = previous {
+            p.deadline.cancel();
+        }
+    }
+
+    pub fn end(&self, game: &Game, snake: &Battlesnake) {
+        self.active
+            .lock()
+            .unwrap()
+            .remove(&(game.id.clone(), snake.id.clone()));
+        if let Some(p) = self.take(game, snake) {
+            p.deadline.cancel();
+        }
+    }
+}
+
+/// Board after we play `my_move` and every opponent plays the move of the simple strategy.
+/// No food is spawned. Returns `None` if we do not survive the turn.
+pub fn predict_next_board(
+    game: &Game,
+    board: &Board,
+    snake: &Battlesnake,
+    my_move: Direction,
+    seed: u64,
+    turn: i32,
+) -> Option<Board> {
+    let opponent_strategy = SimpleStrategy::default();
+    let mut moves = board
+        .snakes
+        .iter()
+        .filter(|s| s.id != snake.id)
+        .map(|s| {
+            let mut rng = move_rng(seed, &game.id, &s.id, turn);
+            let mv = opponent_strategy.make_move(game, board, s, &mut rng);
+            (s.id.clone(), mv.dir)
+        })
+        .collect::<HashMap<_, _>>();
+    moves.insert(snake.id.clone(), my_move);
+
+    let mut next = board.clone();
+    let eliminated = step(
+        &mut next,
+        &moves,
+        &Ruleset::no_spawn(),
+        &mut StdRng::seed_from_u64(0),
+    );
+    if eliminated.iter().any(|(id, _)| *id == snake.id) {
+        return None;
+    }
+    Some(next)
+}
+
+/// Whether both boards hold the same snakes, food and hazards, regardless of their order
+pub fn same_position(a: &Board, b: &Board) -> bool {
+    let sorted = |cells: &[_]| {
+        let mut cells = cells.to_vec();
+        cells.sort();
+        cells
+    };
+    a.width == b.width
+        && a.height == b.height
+        && a.snakes.len() == b.snakes.len()
+        && a.snakes.iter().all(|s| {
+            b.snakes
+                .iter()
+                .any(|o| o.id == s.id && o.body == s.body && o.health == s.health)
+        })
+        && sorted(&a.food) == sorted(&b.food)
+        && sorted(&a.hazards) == sorted(&b.hazards)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Coord;
+    use crate::test_support::{board, board_with_food, snake};
+
+    fn game() -> Game {
+        Game {
+            id: "game".to_string(),
+            ruleset: HashMap::new(),
+            timeout: 500,
+        }
+    }
+
+    fn me() -> Battlesnake {
+        snake(
+            "me",
+            vec![
+                Coord { x: 1, y: 1 },
+                Coord { x: 1, y: 0 },
+                Coord { x: 2, y: 0 },
+            ],
+        )
+    }
+
+    fn other() -> Battlesnake {
+        snake("other", vec![Coord { x: 5, y: 5 }, Coord { x: 5, y: 6 }])
+    }
+
+    #[test]
+    fn test_prediction_moves_every_snake() {
+        let b = board_with_food(7, 7, vec![me(), other()], vec![Coord { x: 3, y: 3 }]);
+        let next = predict_next_board(&game(), &b, &me(), Direction::Up, 0, 1).unwrap();
+
+        let us = next.snakes.iter().find(|s| s.id == "me").unwrap();
+        assert_eq!(us.head, Coord { x: 1, y: 2 });
+        let them = next.snakes.iter().find(|s| s.id == "other").unwrap();
+        assert_ne!(them.head, other().head);
+        assert_eq!(them.health, 99);
+        // Food is never spawned
+        assert_eq!(next.food, vec![Coord { x: 3, y: 3 }]);
+    }
+
+    #[test]
+    fn test_no_prediction_if_we_die() {
+        let b = board(7, 7, vec![me(), other()]);
+        assert!(predict_next_board(&game(), &b, &me(), Direction::Down, 0, 1).is_none());
+    }
+
+    #[test]
+    fn test_same_position_ignores_order() {
+        let a = board_with_food(
+            7,
+            7,
+            vec![me(), other()],
+            vec![Coord { x: 3, y: 3 }, Coord { x: 0, y: 6 }],
+        );
+        let b = board_with_food(
+            7,
+            7,
+            vec![other(), me()],
+            vec![Coord { x: 0, y: 6 }, Coord { x: 3, y: 3 }],
+        );
+        assert!(same_position(&a, &b));
+
+        let spawned = board_with_food(
+            7,
+            7,
+            vec![me(), other()],
+            vec![
+                Coord { x: 3, y: 3 },
+                Coord { x: 0, y: 6 },
+                Coord { x: 4, y: 4 },
+            ],
+        );
+        assert!(!same_position(&a, &spawned));
+
+        let mut hungry = a.clone();
+        hungry.snakes[0].health -= 1;
+        assert!(!same_position(&a, &hungry));
+    }
+
+    #[test]
+    fn test_sessions_cancel_replaced_predictions() {
+        let runtime = rocket::tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let sessions = Sessions::default();
+            let prediction = || Prediction {
+                board: Arc::new(OnceLock::from(board(7, 7, vec![me()]))),
+                deadline: Deadline::never(),
+                started: Instant::now(),
+                search: rocket::tokio::spawn(async { Some(Move { dir: Direction::Up }) }),
+            };
+
+            let first = prediction();
+            let first_deadline = first.deadline.clone();
+            sessions.insert(&game(), &me(), first);
+            sessions.insert(&game(), &me(), prediction());
+            assert!(first_deadline.expired());
+
+            let second = sessions.take(&game(), &me()).unwrap();
+            assert!(!second.deadline.expired());
+            assert!(second.matches(&board(7, 7, vec![me()])));
+            assert!(sessions.take(&game(), &me()).is_none());
+        });
+    }
+
+    #[test]
+    fn test_active_games() {
+        let sessions = Sessions::default();
+        let other_game = Game {
+            id: "another".to_string(),
+            ..game()
+        };
+        sessions.start(&game(), &me());
+        sessions.start(&game(), &me());
+        sessions.start(&game(), &other());
+        sessions.start(&other_game, &me());
+        assert_eq!(sessions.active_games(), vec!["another", "game"]);
+
+        sessions.end(&game(), &me());
+        sessions.end(&other_game, &me());
+        assert_eq!(sessions.active_games(), vec!["game"]);
+        sessions.end(&game(), &other());
+        assert!(sessions.active_games().is_empty());
+    }
+
+    #[test]
+    fn test_games_without_end_expire() {
+        let sessions = Sessions::default();
+        let quick = Game {
+            timeout: 1,
+            ..game()
+        };
+        sessions.start(&quick, &me());
+        assert_eq!(sessions.active_games(), vec!["game"]);
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(sessions.active_games().is_empty());
+
+        // Also without anyone asking for the active games
+        sessions.start(&quick, &me());
+        std::thread::sleep(Duration::from_millis(20));
+        sessions.start(&game(), &other());
+        assert_eq!(sessions.active.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,661 @@
+use crate::game::{get_direction_from_to, Battlesnake, Board, Coord, Direction};
+use rand::prelude::SliceRandom;
+use rand::Rng;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+
+// Local implementation of the standard ruleset
+// See https://docs.battlesnake.com/guides/game/rules
+#[derive(Debug, Clone)]
+pub struct Ruleset {
+    pub food_spawn_chance: u32,
+    pub minimum_food: usize,
+    pub hazard_damage_per_turn: i32,
+}
+
+impl Default for Ruleset {
+    fn default() -> Self {
+        Ruleset {
+            food_spawn_chance: 15,
+            minimum_food: 1,
+            hazard_damage_per_turn: 14,
+        }
+    }
+}
+
+impl Ruleset {
+    /// Standard rules without new food, for looking ahead where spawns can't be predicted
+    pub fn no_spawn() -> Ruleset {
+        Ruleset {
+            food_spawn_chance: 0,
+            minimum_food: 0,
+            ..Ruleset::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "cause", content = "with", rename_all = "kebab-case")]
+pub enum DeathCause {
+    Wall,
+    SelfCollision,
+    BodyCollision(String),
+    HeadToHead(String),
+    Starvation,
+    Hazard,
+}
+
+impl DeathCause {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DeathCause::Wall => "wall",
+            DeathCause::SelfCollision => "self-collision",
+            DeathCause::BodyCollision(_) => "body-collision",
+            DeathCause::HeadToHead(_) => "head-to-head",
+            DeathCause::Starvation => "starvation",
+            DeathCause::Hazard => "hazard",
+        }
+    }
+
+    /// Id of the other snake involved in the elimination
+    pub fn other_snake(&self) -> Option<&str> {
+        match self {
+            DeathCause::BodyCollision(id) | DeathCause::HeadToHead(id) => Some(id),
+            _ => None,
+        }
+    }
+}
+
+impl Display for DeathCause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.other_snake() {
+            Some(id) => write!(f, "{}:{}", self.kind(), id),
+            None => write!(f, "{}", self.kind()),
+        }
+    }
+}
+
+const SNAKE_MAX_HEALTH: i32 = 100;
+const SNAKE_START_SIZE: usize = 3;
+
+// Corners and the middles of the edges, one cell away from the walls
+fn start_candidates(width: i32, height: i32) -> (Vec<Coord>, Vec<Coord>) {
+    let (mn_x, md_x, mx_x) = (1, (width - 1) / 2, width - 2);
+    let (mn_y, md_y, mx_y) = (1, (height - 1) / 2, height - 2);
+
+    let corners = vec![
+        Coord { x: mn_x, y: mn_y },
+        Coord { x: mn_x, y: mx_y },
+        Coord { x: mx_x, y: mn_y },
+        Coord { x: mx_x, y: mx_y },
+    ];
+    let cardinals = vec![
+        Coord { x: mn_x, y: md_y },
+        Coord { x: md_x, y: mn_y },
+        Coord { x: md_x, y: mx_y },
+        Coord { x: mx_x, y: md_y },
+    ];
+    (corners, cardinals)
+}
+
+/// Number of snakes `initial_board` has starting positions for, small boards have fewer
+pub fn max_snakes(width: i32, height: i32) -> usize {
+    if width < 3 || height < 3 {
+        return 0;
+    }
+    let (mut points, cardinals) = start_candidates(width, height);
+    points.extend(cardinals);
+    points.sort();
+    points.dedup();
+    points.len()
+}
+
+pub fn initial_board<R: Rng + ?Sized>(
+    width: i32,
+    height: i32,
+    snake_names: &[&str],
+    rng: &mut R,
+) -> Board {
+    assert!(width >= 3 && height >= 3, "Board must be at least 3x3");
+
+    let (mut corners, mut cardinals) = start_candidates(width, height);
+    corners.shuffle(rng);
+    cardinals.shuffle(rng);
+    let mut start_points: Vec<Coord> = vec![];
+    for c in corners.into_iter().chain(cardinals) {
+        if !start_points.contains(&c) {
+            start_points.push(c);
+        }
+    }
+
+    assert!(
+        snake_names.len() <= start_points.len(),
+        "Too many snakes for the starting positions"
+    );
+
+    let snakes = snake_names
+        .iter()
+        .zip(start_points)
+        .enumerate()
+        .map(|(i, (name, start))| Battlesnake {
+            id: format!("snake-{}", i),
+            name: name.to_string(),
+            health: SNAKE_MAX_HEALTH,
+            body: vec![start; SNAKE_START_SIZE],
+            head: start,
+            length: SNAKE_START_SIZE as i32,
+            latency: "0".to_string(),
+            shout: None,
+        })
+        .collect::<Vec<_>>();
+
+    // Place one food diagonally next to each snake, towards the center
+    let center = Coord {
+        x: (width - 1) / 2,
+        y: (height - 1) / 2,
+    };
+    let mut food = Vec::new();
+    for snake in &snakes {
+        let candidate = Coord {
+            x: snake.head.x + (center.x - snake.head.x).signum(),
+            y: snake.head.y + (center.y - snake.head.y).signum(),
+        };
+        if candidate != snake.head
+            && candidate != center
+            && !food.contains(&candidate)
+            && !snakes.iter().any(|s| s.head == candidate)
+        {
+            food.push(candidate);
+        }
+    }
+    if !snakes.iter().any(|s| s.head == center) {
+        food.push(center);
+    }
+
+    Board {
+        height,
+        width,
+        food,
+        snakes,
+        hazards: vec![],
+    }
+}
+
+/// Advances the board by one turn and returns the ids of all eliminated snakes with the cause.
+/// Snakes without an entry in `moves` keep moving in their current direction.
+pub fn step<R: Rng + ?Sized>(
+    board: &mut Board,
+    moves: &HashMap<String, Direction>,
+    ruleset: &Ruleset,
+    rng: &mut R,
+) -> Vec<(String, DeathCause)> {
+    move_snakes(board, moves);
+    reduce_health(board, ruleset);
+    feed_snakes(board);
+    spawn_food(board, ruleset, rng);
+    eliminate_snakes(board)
+}
+
+fn current_direction(snake: &Battlesnake) -> Direction {
+    snake
+        .body
+        .get(1)
+        .and_then(|neck| get_direction_from_to(neck, &snake.head))
+        .unwrap_or(Direction::Up)
+}
+
+fn move_snakes(board: &mut Board, moves: &HashMap<String, Direction>) {
+    for snake in board.snakes.iter_mut() {
+        let dir = moves
+            .get(&snake.id)
+            .copied()
+            .unwrap_or_else(|| current_direction(snake));
+        let new_head = snake.head.next_coord_in_dir(&dir);
+        snake.body.insert(0, new_head);
+        snake.body.pop();
+        snake.head = new_head;
+    }
+}
+
+fn reduce_health(board: &mut Board, ruleset: &Ruleset) {
+    for snake in board.snakes.iter_mut() {
+        snake.health -= 1;
+        if board.hazards.contains(&snake.head) && !board.food.contains(&snake.head) {
+            snake.health = (snake.health - ruleset.hazard_damage_per_turn).max(0);
+        }
+    }
+}
+
+fn feed_snakes(board: &mut Board) {
+    let mut eaten = Vec::new();
+    for snake in board.snakes.iter_mut() {
+        if board.food.contains(&snake.head) {
+            snake.health = SNAKE_MAX_HEALTH;
+            let tail = *snake.body.last().unwrap();
+            snake.body.push(tail);
+            snake.length = snake.body.len() as i32;
+            eaten.push(snake.head);
+        }
+    }
+    board.food.retain(|f| !eaten.contains(f));
+}
+
+fn spawn_food<R: Rng + ?Sized>(board: &mut Board, ruleset: &Ruleset, rng: &mut R) {
+    let num_to_spawn = if board.food.len() < ruleset.minimum_food {
+        ruleset.minimum_food - board.food.len()
+    } else if ruleset.food_spawn_chance > 0 && rng.random_range(0..100) < ruleset.food_spawn_chance
+    {
+        1
+    } else {
+        0
+    };
+
+    if num_to_spawn == 0 {
+        return;
+    }
+
+    let mut free = (0..board.width)
+        .flat_map(|x| (0..board.height).map(move |y| Coord { x, y }))
+        .filter(|c| !board.food.contains(c))
+        .filter(|c| !board.snakes.iter().any(|s| s.body.contains(c)))
+        .collect::<Vec<_>>();
+    free.shuffle(rng);
+    board.food.extend(free.into_iter().take(num_to_spawn));
+}
+
+fn out_of_bounds(board: &Board, c: &Coord) -> bool {
+    c.x < 0 || c.y < 0 || c.x >= board.width || c.y >= board.height
+}
+
+fn health_or_bounds_cause(board: &Board, snake: &Battlesnake) -> Option<DeathCause> {
+    if out_of_bounds(board, &snake.head) {
+        Some(DeathCause::Wall)
+    } else if snake.health <= 0 && board.hazards.contains(&snake.head) {
+        Some(DeathCause::Hazard)
+    } else if snake.health <= 0 {
+        Some(DeathCause::Starvation)
+    } else {
+        None
+    }
+}
+
+fn collision_cause<'a>(
+    snake: &Battlesnake,
+    others: impl Iterator<Item = &'a Battlesnake> + Clone,
+) -> Option<DeathCause> {
+    // Bodies are not validated on `/end`, they may be empty
+    if snake.body.iter().skip(1).any(|c| *c == snake.head) {
+        return Some(DeathCause::SelfCollision);
+    }
+    let mut others = others.filter(|o| o.id != snake.id);
+    if let Some(other) = others
+        .clone()
+        .find(|o| o.body.iter().skip(1).any(|c| *c == snake.head))
+    {
+        return Some(DeathCause::BodyCollision(other.id.clone()));
+    }
+    others
+        .find(|o| o.head == snake.head && snake.body.len() <= o.body.len())
+        .map(|o| DeathCause::HeadToHead(o.id.clone()))
+}
+
+/// Classifies why `snake` was eliminated, given the board right after its final move.
+/// Works on boards produced by `step` as well as on the final state sent to `/end`.
+pub fn elimination_cause(board: &Board, snake: &Battlesnake) -> Option<DeathCause> {
+    health_or_bounds_cause(board, snake).or_else(|| collision_cause(snake, board.snakes.iter()))
+}
+
+fn eliminate_snakes(board: &mut Board) -> Vec<(String, DeathCause)> {
+    let mut eliminated = board
+        .snakes
+        .iter()
+        .filter_map(|s| health_or_bounds_cause(board, s).map(|c| (s.id.clone(), c)))
+        .collect::<Vec<_>>();
+
+    // Snakes eliminated by health or bounds can't cause collisions anymore
+    let remaining = board
+        .snakes
+        .iter()
+        .filter(|s| !eliminated.iter().any(|(id, _)| *id == s.id))
+        .collect::<Vec<_>>();
+
+    let collided = remaining
+        .iter()
+        .filter_map(|s| collision_cause(s, remaining.iter().copied()).map(|c| (s.id.clone(), c)))
+        .collect::<Vec<_>>();
+
+    eliminated.extend(collided);
+    board
+        .snakes
+        .retain(|s| !eliminated.iter().any(|(id, _)| *id == s.id));
+    eliminated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{board, board_with_food, snake_with_health};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_move_and_reduce_health() {
+        let mut b = board(
+            5,
+            5,
+            vec![snake_with_health(
+                "a",
+                vec![
+                    Coord { x: 2, y: 2 },
+                    Coord { x: 2, y: 1 },
+                    Coord { x: 2, y: 0 },
+                ],
+                50,
+            )],
+        );
+        let moves = HashMap::from([("a".to_string(), Direction::Up)]);
+        let eliminated = step(
+            &mut b,
+            &moves,
+            &Ruleset::no_spawn(),
+            &mut StdRng::seed_from_u64(0),
+        );
+
+        assert!(eliminated.is_empty());
+        assert_eq!(b.snakes[0].head, Coord { x: 2, y: 3 });
+        assert_eq!(
+            b.snakes[0].body,
+            vec![
+                Coord { x: 2, y: 3 },
+                Coord { x: 2, y: 2 },
+                Coord { x: 2, y: 1 }
+            ]
+        );
+        assert_eq!(b.snakes[0].health, 49);
+    }
+
+    #[test]
+    fn test_feeding_grows_snake() {
+        let mut b = board_with_food(
+            5,
+            5,
+            vec![snake_with_health(
+                "a",
+                vec![
+                    Coord { x: 2, y: 2 },
+                    Coord { x: 2, y: 1 },
+                    Coord { x: 2, y: 0 },
+                ],
+                50,
+            )],
+            vec![Coord { x: 1, y: 2 }],
+        );
+        let moves = HashMap::from([("a".to_string(), Direction::Left)]);
+        step(
+            &mut b,
+            &moves,
+            &Ruleset::no_spawn(),
+            &mut StdRng::seed_from_u64(0),
+        );
+
+        assert_eq!(b.snakes[0].health, 100);
+        assert_eq!(b.snakes[0].length, 4);
+        assert!(b.food.is_empty());
+    }
+
+    #[test]
+    fn test_wall_and_starvation() {
+        let mut b = board(
+            5,
+            5,
+            vec![
+                snake_with_health("wall", vec![Coord { x: 0, y: 0 }, Coord { x: 1, y: 0 }], 50),
+                snake_with_health(
+                    "starve",
+                    vec![Coord { x: 3, y: 3 }, Coord { x: 3, y: 2 }],
+                    1,
+                ),
+            ],
+        );
+        let moves = HashMap::from([
+            ("wall".to_string(), Direction::Left),
+            ("starve".to_string(), Direction::Up),
+        ]);
+        let mut eliminated = step(
+            &mut b,
+            &moves,
+            &Ruleset::no_spawn(),
+            &mut StdRng::seed_from_u64(0),
+        );
+        eliminated.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(
+            eliminated,
+            vec![
+                ("starve".to_string(), DeathCause::Starvation),
+                ("wall".to_string(), DeathCause::Wall)
+            ]
+        );
+        assert!(b.snakes.is_empty());
+    }
+
+    #[test]
+    fn test_head_to_head_longer_survives() {
+        let mut b = board(
+            5,
+            5,
+            vec![
+                snake_with_health(
+                    "long",
+                    vec![
+                        Coord { x: 1, y: 2 },
+                        Coord { x: 0, y: 2 },
+                        Coord { x: 0, y: 1 },
+                    ],
+                    50,
+                ),
+                snake_with_health(
+                    "short",
+                    vec![Coord { x: 3, y: 2 }, Coord { x: 4, y: 2 }],
+                    50,
+                ),
+            ],
+        );
+        let moves = HashMap::from([
+            ("long".to_string(), Direction::Right),
+            ("short".to_string(), Direction::Left),
+        ]);
+        let eliminated = step(
+            &mut b,
+            &moves,
+            &Ruleset::no_spawn(),
+            &mut StdRng::seed_from_u64(0),
+        );
+
+        assert_eq!(
+            eliminated,
+            vec![(
+                "short".to_string(),
+                DeathCause::HeadToHead("long".to_string())
+            )]
+        );
+        assert_eq!(b.snakes.len(), 1);
+    }
+
+    #[test]
+    fn test_body_collision() {
+        let mut b = board(
+            5,
+            5,
+            vec![
+                snake_with_health(
+                    "a",
+                    vec![
+                        Coord { x: 1, y: 2 },
+                        Coord { x: 0, y: 2 },
+                        Coord { x: 0, y: 1 },
+                    ],
+                    50,
+                ),
+                snake_with_health(
+                    "b",
+                    vec![
+                        Coord { x: 2, y: 3 },
+                        Coord { x: 2, y: 2 },
+                        Coord { x: 2, y: 1 },
+                    ],
+                    50,
+                ),
+            ],
+        );
+        let moves = HashMap::from([
+            ("a".to_string(), Direction::Right),
+            ("b".to_string(), Direction::Up),
+        ]);
+        let eliminated = step(
+            &mut b,
+            &moves,
+            &Ruleset::no_spawn(),
+            &mut StdRng::seed_from_u64(0),
+        );
+
+        assert_eq!(
+            eliminated,
+            vec![("a".to_string(), DeathCause::BodyCollision("b".to_string()))]
+        );
+        assert_eq!(b.snakes[0].id, "b");
+    }
+
+    #[test]
+    fn test_self_collision_and_hazard() {
+        let mut b = board(
+            5,
+            5,
+            vec![
+                snake_with_health(
+                    "curl",
+                    vec![
+                        Coord { x: 1, y: 1 },
+                        Coord { x: 1, y: 2 },
+                        Coord { x: 0, y: 2 },
+                        Coord { x: 0, y: 1 },
+                        Coord { x: 0, y: 0 },
+                    ],
+                    50,
+                ),
+                snake_with_health("burn", vec![Coord { x: 3, y: 3 }, Coord { x: 3, y: 2 }], 10),
+            ],
+        );
+        b.hazards = vec![Coord { x: 3, y: 4 }];
+        let moves = HashMap::from([
+            ("curl".to_string(), Direction::Left),
+            ("burn".to_string(), Direction::Up),
+        ]);
+        let mut eliminated = step(
+            &mut b,
+            &moves,
+            &Ruleset::no_spawn(),
+            &mut StdRng::seed_from_u64(0),
+        );
+        eliminated.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(
+            eliminated,
+            vec![
+                ("burn".to_string(), DeathCause::Hazard),
+                ("curl".to_string(), DeathCause::SelfCollision)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_elimination_cause_from_final_board() {
+        // Final board as sent to /end: the loser already moved onto the winner's head
+        let winner = snake_with_health(
+            "winner",
+            vec![
+                Coord { x: 2, y: 2 },
+                Coord { x: 1, y: 2 },
+                Coord { x: 0, y: 2 },
+            ],
+            50,
+        );
+        let loser = snake_with_health(
+            "loser",
+            vec![Coord { x: 2, y: 2 }, Coord { x: 3, y: 2 }],
+            50,
+        );
+        let b = board(5, 5, vec![winner]);
+
+        assert_eq!(
+            elimination_cause(&b, &loser),
+            Some(DeathCause::HeadToHead("winner".to_string()))
+        );
+        assert_eq!(elimination_cause(&b, &b.snakes[0]), None);
+
+        // Requests to /end are not validated, an empty body must not panic
+        let mut empty = loser.clone();
+        empty.body.clear();
+        assert_eq!(
+            elimination_cause(&b, &empty),
+            Some(DeathCause::HeadToHead("winner".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_minimum_food_is_spawned() {
+        let mut b = board(
+            5,
+            5,
+            vec![snake_with_health(
+                "a",
+                vec![Coord { x: 2, y: 2 }, Coord { x: 2, y: 1 }],
+                50,
+            )],
+        );
+        let ruleset = Ruleset {
+            food_spawn_chance: 0,
+            minimum_food: 2,
+            ..Ruleset::default()
+        };
+        step(
+            &mut b,
+            &HashMap::new(),
+            &ruleset,
+            &mut StdRng::seed_from_u64(0),
+        );
+
+        assert_eq!(b.food.len(), 2);
+        assert!(b.food.iter().all(|f| !b.snakes[0].body.contains(f)));
+    }
+
+    #[test]
+    fn test_max_snakes() {
+        assert_eq!(max_snakes(11, 11), 8);
+        assert_eq!(max_snakes(4, 4), 4);
+        assert_eq!(max_snakes(3, 3), 1);
+        assert_eq!(max_snakes(2, 11), 0);
+        for n in 1..=4 {
+            let names = vec!["s"; n];
+            let b = initial_board(4, 4, &names, &mut StdRng::seed_from_u64(n as u64));
+            let mut heads = b.snakes.iter().map(|s| s.head).collect::<Vec<_>>();
+            heads.sort();
+            heads.dedup();
+            assert_eq!(heads.len(), n);
+        }
+    }
+
+    #[test]
+    fn test_initial_board() {
+        let b = initial_board(11, 11, &["a", "b", "c", "d"], &mut StdRng::seed_from_u64(1));
+
+        assert_eq!(b.snakes.len(), 4);
+        assert!(b
+            .snakes
+            .iter()
+            .all(|s| s.body.len() == 3 && s.health == 100));
+        assert!(b.food.contains(&Coord { x: 5, y: 5 }));
+        assert_eq!(b.food.len(), 5);
+    }
+}
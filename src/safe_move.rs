@@ -0,0 +1,322 @@
+use crate::game::{all_directions, Battlesnake, Board, Coord, Direction};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::cmp::Reverse;
+
+/// How likely a move survives the next turn. Works on any coordinates, so it can also be
+/// used for boards that failed validation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoveSafety {
+    pub on_board: bool,
+    /// Not into a body part that is still there next turn
+    pub free: bool,
+    /// Opponents at least as long as we are that can move onto the same cell
+    pub head_to_head_threats: usize,
+    /// Free cells next to the target cell
+    pub space: usize,
+}
+
+impl MoveSafety {
+    /// Larger is safer, the fields in order of importance
+    pub fn rank(&self) -> (bool, bool, Reverse<usize>, usize) {
+        (
+            self.on_board,
+            self.free,
+            Reverse(self.head_to_head_threats),
+            self.space,
+        )
+    }
+}
+
+/// Cell one step from `from`. On boards that wrap, moves across an edge come back in on the
+/// opposite side.
+pub fn step(board: &Board, from: Coord, dir: Direction, wrap: bool) -> Coord {
+    let next = from.next_coord_in_dir(&dir);
+    if !wrap {
+        return next;
+    }
+    Coord {
+        x: next.x.rem_euclid(board.width.max(1)),
+        y: next.y.rem_euclid(board.height.max(1)),
+    }
+}
+
+// Body parts of `other` that are still occupied after `snake` moves. Tails move away unless
+// the snake just ate and its tail is stacked. The other snakes keep their tail as well if they
+// can eat this turn, as we don't know whether they will.
+fn remaining_body<'a>(
+    board: &Board,
+    snake: &Battlesnake,
+    other: &'a Battlesnake,
+    wrap: bool,
+) -> &'a [Coord] {
+    let len = other.body.len();
+    let tail_stays = len >= 2 && other.body[len - 1] == other.body[len - 2];
+    let may_eat = other.id != snake.id
+        && all_directions()
+            .into_iter()
+            .any(|d| board.food.contains(&step(board, other.head, d, wrap)));
+    let keep = if tail_stays || may_eat {
+        len
+    } else {
+        len.saturating_sub(1)
+    };
+    &other.body[..keep]
+}
+
+/// Body parts of all snakes that are still occupied after `snake` moves
+pub fn occupied_next_turn(board: &Board, snake: &Battlesnake, wrap: bool) -> Vec<Coord> {
+    board
+        .snakes
+        .iter()
+        .flat_map(|s| remaining_body(board, snake, s, wrap))
+        .copied()
+        .collect()
+}
+
+/// Whether `c` is still occupied by a body part after `snake` moves
+pub fn is_occupied_next_turn(board: &Board, snake: &Battlesnake, wrap: bool, c: &Coord) -> bool {
+    board
+        .snakes
+        .iter()
+        .any(|s| remaining_body(board, snake, s, wrap).contains(c))
+}
+
+/// Safety of each of the four moves of `snake`, in the order of `all_directions`. On boards
+/// that wrap every move stays on the board.
+pub fn move_safety(board: &Board, snake: &Battlesnake, wrap: bool) -> Vec<(Direction, MoveSafety)> {
+    let on_board = |c: &Coord| c.x >= 0 && c.y >= 0 && c.x < board.width && c.y < board.height;
+    let occupied = occupied_next_turn(board, snake, wrap);
+    let threats = board
+        .snakes
+        .iter()
+        .filter(|s| s.id != snake.id && s.body.len() >= snake.body.len())
+        .map(|s| s.head)
+        .collect::<Vec<_>>();
+
+    all_directions()
+        .into_iter()
+        .map(|d| {
+            let next = step(board, snake.head, d, wrap);
+            let safety = MoveSafety {
+                on_board: wrap || on_board(&next),
+                free: !occupied.contains(&next),
+                head_to_head_threats: threats
+                    .iter()
+                    .filter(|h| {
+                        all_directions()
+                            .into_iter()
+                            .any(|n| step(board, **h, n, wrap) == next)
+                    })
+                    .count(),
+                space: all_directions()
+                    .into_iter()
+                    .map(|n| step(board, next, n, wrap))
+                    .filter(|n| on_board(n) && !occupied.contains(n) && *n != snake.head)
+                    .count(),
+            };
+            (d, safety)
+        })
+        .collect()
+}
+
+/// Last-resort move: the safest of the four directions, ties go to the first one.
+pub fn safe_move(board: &Board, snake: &Battlesnake, wrap: bool) -> Direction {
+    let mut best: Option<(Direction, MoveSafety)> = None;
+    for (d, safety) in move_safety(board, snake, wrap) {
+        if best.is_none_or(|(_, b)| safety.rank() > b.rank()) {
+            best = Some((d, safety));
+        }
+    }
+    best.map_or(Direction::Up, |(d, _)| d)
+}
+
+/// Safest move for a request that is not a valid game state, from the parts of it that can
+/// be read: the board and our snake if they parse on their own, otherwise just our head and
+/// the board size. `None` if not even those are there.
+pub fn salvage_move(request: &str) -> Option<Direction> {
+    let value = serde_json::from_str::<Value>(request).ok()?;
+    fn parse<T: DeserializeOwned>(v: &Value) -> Option<T> {
+        serde_json::from_value(v.clone()).ok()
+    }
+
+    let you = match parse(&value["you"]) {
+        Some(you) => you,
+        None => {
+            let head: Coord = parse(&value["you"]["head"])?;
+            Battlesnake {
+                id: value["you"]["id"].as_str().unwrap_or_default().to_string(),
+                name: String::new(),
+                health: 100,
+                body: parse(&value["you"]["body"]).unwrap_or(vec![head]),
+                head,
+                length: 1,
+                latency: String::new(),
+                shout: None,
+            }
+        }
+    };
+    let board = match parse(&value["board"]) {
+        Some(board) => board,
+        None => Board {
+            width: value["board"]["width"].as_i64()? as i32,
+            height: value["board"]["height"].as_i64()? as i32,
+            food: vec![],
+            snakes: vec![you.clone()],
+            hazards: vec![],
+        },
+    };
+    let wrap = value["game"]["ruleset"]["name"].as_str() == Some("wrapped");
+    Some(safe_move(&board, &you, wrap))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{board, snake};
+
+    #[test]
+    fn test_avoids_walls_and_bodies() {
+        // In the bottom left corner, our body blocks the way up
+        let me = snake(
+            "me",
+            vec![
+                Coord { x: 0, y: 0 },
+                Coord { x: 0, y: 1 },
+                Coord { x: 0, y: 2 },
+            ],
+        );
+        let b = board(5, 5, vec![me.clone()]);
+        assert_eq!(safe_move(&b, &me, false), Direction::Right);
+
+        let safety = move_safety(&b, &me, false);
+        assert!(!safety[0].1.on_board);
+        assert!(safety[1].1.on_board && safety[1].1.free);
+    }
+
+    #[test]
+    fn test_moves_into_vacating_tail() {
+        // Boxed in by our own body, only the tail cell frees up
+        let me = snake(
+            "me",
+            vec![
+                Coord { x: 0, y: 0 },
+                Coord { x: 0, y: 1 },
+                Coord { x: 1, y: 1 },
+                Coord { x: 1, y: 0 },
+            ],
+        );
+        let b = board(5, 5, vec![me.clone()]);
+        assert_eq!(safe_move(&b, &me, false), Direction::Right);
+
+        // Unless we just ate
+        let mut fed = me.clone();
+        fed.body.push(Coord { x: 1, y: 0 });
+        let b = board(5, 5, vec![fed.clone()]);
+        assert!(move_safety(&b, &fed, false)
+            .iter()
+            .all(|(_, s)| !(s.on_board && s.free)));
+    }
+
+    #[test]
+    fn test_tail_of_snake_next_to_food_stays() {
+        let me = snake("me", vec![Coord { x: 2, y: 2 }, Coord { x: 2, y: 1 }]);
+        // The other tail is right of our head
+        let other = snake(
+            "other",
+            vec![
+                Coord { x: 4, y: 3 },
+                Coord { x: 4, y: 2 },
+                Coord { x: 3, y: 2 },
+            ],
+        );
+        let mut b = board(5, 5, vec![me.clone(), other]);
+        assert!(move_safety(&b, &me, false)[1].1.free);
+
+        // If it eats, its tail stays where it is
+        b.food = vec![Coord { x: 4, y: 4 }];
+        assert!(!move_safety(&b, &me, false)[1].1.free);
+    }
+
+    #[test]
+    fn test_wrapped_moves_stay_on_board() {
+        let me = snake(
+            "me",
+            vec![
+                Coord { x: 0, y: 0 },
+                Coord { x: 0, y: 1 },
+                Coord { x: 0, y: 2 },
+            ],
+        );
+        let other = snake(
+            "other",
+            vec![
+                Coord { x: 4, y: 4 },
+                Coord { x: 4, y: 3 },
+                Coord { x: 4, y: 2 },
+            ],
+        );
+        let b = board(5, 5, vec![me.clone(), other]);
+        assert!(!move_safety(&b, &me, false)[0].1.on_board);
+
+        let safety = move_safety(&b, &me, true);
+        assert!(safety.iter().all(|(_, s)| s.on_board));
+        // Left comes back in at (4,0) and down at (0,4), both next to the other head
+        assert!(safety[0].1.free);
+        assert_eq!(safety[0].1.head_to_head_threats, 1);
+        assert_eq!(safety[3].1.head_to_head_threats, 1);
+        assert_eq!(safe_move(&b, &me, true), Direction::Right);
+    }
+
+    #[test]
+    fn test_prefers_moves_without_head_to_head() {
+        let me = snake("me", vec![Coord { x: 2, y: 2 }, Coord { x: 2, y: 1 }]);
+        // A longer snake can also reach (1,2) and (2,3)
+        let other = snake(
+            "other",
+            vec![
+                Coord { x: 1, y: 3 },
+                Coord { x: 0, y: 3 },
+                Coord { x: 0, y: 4 },
+            ],
+        );
+        let b = board(5, 5, vec![me.clone(), other]);
+        let safety = move_safety(&b, &me, false);
+        assert_eq!(safety[0].1.head_to_head_threats, 1);
+        assert_eq!(safety[2].1.head_to_head_threats, 1);
+        assert_eq!(safe_move(&b, &me, false), Direction::Right);
+    }
+
+    #[test]
+    fn test_salvages_move_from_partial_request() {
+        // Only the head and the board size are usable, on a single row we have to go right
+        let request = r#"{"you": {"head": {"x": 0, "y": 0}}, "board": {"width": 5, "height": 1}}"#;
+        assert_eq!(salvage_move(request), Some(Direction::Right));
+
+        // The board parses on its own, its snakes are taken into account
+        let me = snake(
+            "me",
+            vec![
+                Coord { x: 0, y: 0 },
+                Coord { x: 1, y: 0 },
+                Coord { x: 2, y: 0 },
+            ],
+        );
+        let request = serde_json::json!({
+            "game": {"id": 3},
+            "board": board(5, 5, vec![me.clone()]),
+            "you": me,
+        });
+        assert_eq!(salvage_move(&request.to_string()), Some(Direction::Up));
+
+        assert_eq!(salvage_move(r#"{"you": {"head": {"x": 2, "y": 0}}}"#), None);
+        assert_eq!(salvage_move("{\"you\": "), None);
+    }
+
+    #[test]
+    fn test_off_board_snake_still_gets_a_move() {
+        let me = snake("me", vec![Coord { x: -3, y: 9 }]);
+        let b = board(5, 5, vec![me.clone()]);
+        assert_eq!(safe_move(&b, &me, false), Direction::Left);
+    }
+}
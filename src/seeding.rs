@@ -0,0 +1,59 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+// FNV-1a, unlike `DefaultHasher` it is stable across platforms and Rust versions,
+// so a seed reproduces the same games everywhere
+fn fnv1a(parts: &[&[u8]]) -> u64 {
+    const OFFSET: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    parts.iter().fold(OFFSET, |hash, part| {
+        // Separator so that ("ab", "c") and ("a", "bc") hash differently
+        part.iter()
+            .chain(&[0xff])
+            .fold(hash, |h, b| (h ^ *b as u64).wrapping_mul(PRIME))
+    })
+}
+
+/// Rng for the rules engine of a single game, e.g. start positions and food spawns.
+pub fn game_rng(seed: u64, game_id: &str) -> StdRng {
+    StdRng::seed_from_u64(fnv1a(&[&seed.to_le_bytes(), game_id.as_bytes()]))
+}
+
+/// Rng handed to a strategy for a single decision. The same seed, game, snake and turn
+/// always yield the same random stream, so the same board always yields the same move.
+pub fn move_rng(seed: u64, game_id: &str, snake_id: &str, turn: i32) -> StdRng {
+    StdRng::seed_from_u64(fnv1a(&[
+        &seed.to_le_bytes(),
+        game_id.as_bytes(),
+        snake_id.as_bytes(),
+        &turn.to_le_bytes(),
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_move_rng_is_deterministic() {
+        let a: Vec<u32> = (0..8)
+            .map(|_| move_rng(7, "game", "snake", 3).random())
+            .collect();
+        assert!(a.windows(2).all(|w| w[0] == w[1]));
+    }
+
+    #[test]
+    fn test_move_rng_depends_on_all_inputs() {
+        let base: u64 = move_rng(7, "game", "snake", 3).random();
+        assert_ne!(base, move_rng(8, "game", "snake", 3).random::<u64>());
+        assert_ne!(base, move_rng(7, "game2", "snake", 3).random::<u64>());
+        assert_ne!(base, move_rng(7, "game", "snake2", 3).random::<u64>());
+        assert_ne!(base, move_rng(7, "game", "snake", 4).random::<u64>());
+        assert_ne!(
+            fnv1a(&[b"ab".as_slice(), b"c".as_slice()]),
+            fnv1a(&[b"a".as_slice(), b"bc".as_slice()])
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use crate::game::{Board, Direction, Game};
+use crate::rules::{initial_board, max_snakes, step, DeathCause, Ruleset};
+use crate::seeding::{game_rng, move_rng};
+use crate::strategy::Strategy;
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub struct SimulationSettings {
+    pub width: i32,
+    pub height: i32,
+    pub max_turns: u32,
+    pub ruleset: Ruleset,
+}
+
+impl SimulationSettings {
+    /// Whether the board has starting positions for `players` snakes
+    pub fn check_players(&self, players: usize) -> Result<(), String> {
+        let room = max_snakes(self.width, self.height);
+        if players > room {
+            return Err(format!(
+                "A {}x{} board has room for {} snakes, not {}",
+                self.width, self.height, room, players
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GameRecord {
+    pub turns: u32,
+    /// Placement per participant (1 = winner). Snakes eliminated in the same turn share a place.
+    pub placements: Vec<usize>,
+    /// Cause of elimination per participant, `None` if it survived until the end.
+    pub causes: Vec<Option<DeathCause>>,
+    /// Snake id assigned to each participant on the board.
+    pub snake_ids: Vec<String>,
+}
+
+pub fn simulation_game(id: &str) -> Game {
+    Game {
+        id: id.to_string(),
+        ruleset: HashMap::from([("name".to_string(), Value::from("standard"))]),
+        timeout: 500,
+    }
+}
+
+/// Plays a full local game between the given strategies. Solo games run until the snake dies,
+/// all other games until at most one snake is left; both are capped at `max_turns`.
+/// The game is fully determined by `seed` and `game_id`.
+pub fn play_game(
+    game_id: &str,
+    participants: &[(&str, &dyn Strategy)],
+    settings: &SimulationSettings,
+    seed: u64,
+) -> GameRecord {
+    let names = participants.iter().map(|(n, _)| *n).collect::<Vec<_>>();
+    let game = simulation_game(game_id);
+    let mut rng = game_rng(seed, game_id);
+    let mut board = initial_board(settings.width, settings.height, &names, &mut rng);
+    let ids = board
+        .snakes
+        .iter()
+        .map(|s| s.id.clone())
+        .collect::<Vec<_>>();
+
+    let min_alive = if participants.len() > 1 { 1 } else { 0 };
+    // Turn in which each participant was eliminated, `None` if it survived until the end
+    let mut eliminated_at = vec![None; participants.len()];
+    let mut causes = vec![None; participants.len()];
+    let mut turn = 0;
+
+    while board.snakes.len() > min_alive && turn < settings.max_turns {
+        let moves = collect_moves(&game, &board, turn, &ids, participants, seed);
+        turn += 1;
+        for (id, cause) in step(&mut board, &moves, &settings.ruleset, &mut rng) {
+            if let Some(idx) = ids.iter().position(|i| *i == id) {
+                eliminated_at[idx] = Some(turn);
+                causes[idx] = Some(cause);
+            }
+        }
+    }
+
+    GameRecord {
+        turns: turn,
+        placements: placements(&eliminated_at),
+        causes,
+        snake_ids: ids,
+    }
+}
+
+fn collect_moves(
+    game: &Game,
+    board: &Board,
+    turn: u32,
+    ids: &[String],
+    participants: &[(&str, &dyn Strategy)],
+    seed: u64,
+) -> HashMap<String, Direction> {
+    board
+        .snakes
+        .iter()
+        .filter_map(|snake| {
+            let idx = ids.iter().position(|i| *i == snake.id)?;
+            let mut rng = move_rng(seed, &game.id, &snake.id, turn as i32);
+            let mv = participants[idx].1.make_move(game, board, snake, &mut rng);
+            Some((snake.id.clone(), mv.dir))
+        })
+        .collect()
+}
+
+fn placements(eliminated_at: &[Option<u32>]) -> Vec<usize> {
+    let outlasts = |a: &Option<u32>, b: &Option<u32>| match (a, b) {
+        (None, Some(_)) => true,
+        (Some(x), Some(y)) => x > y,
+        _ => false,
+    };
+    eliminated_at
+        .iter()
+        .map(|me| 1 + eliminated_at.iter().filter(|o| outlasts(o, me)).count())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::{RandomStrategy, SimpleStrategy};
+
+    #[test]
+    fn test_same_seed_replays_same_game() {
+        let settings = SimulationSettings {
+            width: 7,
+            height: 7,
+            max_turns: 200,
+            ruleset: Ruleset::default(),
+        };
+        let participants: [(&str, &dyn Strategy); 2] =
+            [("random", &RandomStrategy), ("simple", &SimpleStrategy::default())];
+
+        let records = (0..2)
+            .map(|_| play_game("replay", &participants, &settings, 42))
+            .collect::<Vec<_>>();
+
+        assert_eq!(records[0].turns, records[1].turns);
+        assert_eq!(records[0].placements, records[1].placements);
+        assert_eq!(records[0].causes, records[1].causes);
+    }
+
+    #[test]
+    fn test_placements() {
+        assert_eq!(placements(&[None, Some(3), Some(10)]), vec![1, 3, 2]);
+        assert_eq!(placements(&[Some(5), Some(5)]), vec![1, 1]);
+        assert_eq!(
+            placements(&[None, None, Some(1), Some(2)]),
+            vec![1, 1, 4, 3]
+        );
+    }
+}
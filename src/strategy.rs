@@ -1,92 +1,116 @@
 use crate::game::{all_directions, Battlesnake, Board, Coord, Direction, Game, Move};
 
+use crate::chokepoint::analyze_region_from;
+use crate::cutoff::find_cutoff;
+use crate::duel::{is_duel_endgame, solve_duel};
+use crate::evaluation::{evaluate_move, EvalWeights};
 use crate::flood_fill::flood_fill;
-use crate::pathfinding::dijkstra;
-use rand::rng;
+use crate::food_policy::FoodPolicy;
+use crate::hamilton::HamiltonStrategy;
+use crate::mcts::TreeCache;
+use crate::move_filter::{is_wrapped, MoveFilters};
+use crate::parallel::Deadline;
+use crate::pathfinding::{astar, direction_to_own_tail};
+use crate::safe_move::safe_move;
+use crate::survival::longest_survival_path;
 
 use rand::prelude::IndexedRandom;
-use rand::Rng;
+use rand::RngCore;
+use std::path::Path;
+use std::sync::Arc;
 
 pub trait Strategy {
-    fn make_move(&self, game: &Game, board: &Board, snake: &Battlesnake) -> Move;
+    /// All randomness has to come from `rng`, so that the same board and rng state
+    /// always result in the same move. Searching strategies are only limited by their node or
+    /// iteration budget here, never by the clock.
+    fn make_move(
+        &self,
+        game: &Game,
+        board: &Board,
+        snake: &Battlesnake,
+        rng: &mut dyn RngCore,
+    ) -> Move;
+
+    /// Like `make_move`, but searching strategies also stop once `deadline` expires. Used by
+    /// the server to answer within the game timeout and to keep searching between turns.
+    fn make_move_until(
+        &self,
+        game: &Game,
+        board: &Board,
+        snake: &Battlesnake,
+        rng: &mut dyn RngCore,
+        _deadline: &Deadline,
+    ) -> Move {
+        self.make_move(game, board, snake, rng)
+    }
+
+    /// Called when `game` ended, strategies drop what they kept about it
+    fn game_over(&self, _game: &Game, _snake: &Battlesnake) {}
 }
 
 pub struct StrategyState {
-    pub strategy: Box<dyn Strategy + Send + Sync>,
+    /// Name the strategy was built from, without its config
+    pub name: String,
+    /// Config part of the strategy spec, e.g. the weights file of `weighted:weights.json`
+    pub config: Option<String>,
+    pub strategy: Arc<dyn Strategy + Send + Sync>,
 }
 
-pub struct RandomStrategy;
+pub const STRATEGY_NAMES: &[&str] = &[
+    "random", "simple", "weighted", "hamilton", "cutoff", "duel", "mcts",
+];
 
-impl Strategy for RandomStrategy {
-    fn make_move(&self, _: &Game, _: &Board, _: &Battlesnake) -> Move {
-        let mut rng = rng();
-        let num = rng.random_range(0..=3);
-
-        let dir = match num {
-            0 => Direction::Up,
-            1 => Direction::Down,
-            2 => Direction::Left,
-            3 => Direction::Right,
-            _ => panic!("Unknown direction {}", num),
-        };
-
-        Move { dir }
+/// Builds a strategy from a spec of the form `name` or `name:config`,
+/// e.g. `weighted:weights.json` loads the evaluation weights from a file
+/// and `simple:hungry:40` only seeks food below 40 health.
+pub fn build_strategy(spec: &str) -> Result<Box<dyn Strategy + Send + Sync>, String> {
+    let (name, config) = match spec.split_once(':') {
+        Some((name, config)) => (name, Some(config)),
+        None => (spec, None),
+    };
+    match (name, config) {
+        ("random", None) => Ok(Box::new(RandomStrategy)),
+        ("simple", None) => Ok(Box::new(SimpleStrategy::default())),
+        ("simple", Some(policy)) => Ok(Box::new(SimpleStrategy {
+            food_policy: FoodPolicy::parse(policy)?,
+        })),
+        ("hamilton", None) => Ok(Box::new(HamiltonStrategy)),
+        ("cutoff", None) => Ok(Box::new(CutoffStrategy)),
+        ("duel", None) => Ok(Box::new(DuelStrategy)),
+        ("mcts", None) => Ok(Box::new(MctsStrategy::default())),
+        ("weighted", None) => Ok(Box::new(WeightedStrategy::default())),
+        ("weighted", Some(path)) => Ok(Box::new(WeightedStrategy {
+            weights: EvalWeights::load(Path::new(path))?,
+        })),
+        _ => Err(format!("Strategy {} not specified", spec)),
     }
 }
 
-pub struct SimpleStrategy;
-
-impl SimpleStrategy {
-    fn prevent_self_collision(&self, moves: &mut Vec<Direction>, snake: &Battlesnake) {
-        let head = &snake.head;
-
-        let pos_up = head.next_coord_in_dir(&Direction::Up);
-        let pos_down = head.next_coord_in_dir(&Direction::Down);
-        let pos_left = head.next_coord_in_dir(&Direction::Left);
-        let pos_right = head.next_coord_in_dir(&Direction::Right);
-
-        snake.body.iter().for_each(|bpos| {
-            if *bpos == pos_left {
-                moves.retain(|mv| *mv != Direction::Left);
-            }
-            if *bpos == pos_right {
-                moves.retain(|mv| *mv != Direction::Right);
-            }
-            if *bpos == pos_up {
-                moves.retain(|mv| *mv != Direction::Up);
-            }
-            if *bpos == pos_down {
-                moves.retain(|mv| *mv != Direction::Down);
-            }
-        })
-    }
+pub struct RandomStrategy;
 
-    fn prevent_out_of_bounds(
+impl Strategy for RandomStrategy {
+    fn make_move(
         &self,
-        moves: &mut Vec<Direction>,
+        game: &Game,
         board: &Board,
         snake: &Battlesnake,
-    ) {
-        let head = &snake.head;
-
-        let b_height = board.height;
-        let b_width = board.width;
-
-        if head.x == 0 {
-            moves.retain(|mv| *mv != Direction::Left);
-        }
-        if head.x == b_width - 1 {
-            moves.retain(|mv| *mv != Direction::Right);
-        }
-        if head.y == 0 {
-            moves.retain(|mv| *mv != Direction::Down);
-        }
-        if head.y == b_height - 1 {
-            moves.retain(|mv| *mv != Direction::Up);
+        rng: &mut dyn RngCore,
+    ) -> Move {
+        // Random among the moves that pass as many of the safety filters as possible
+        let moves = MoveFilters::standard(game).preferred(board, snake);
+        Move {
+            dir: *moves.choose(rng).expect("preferred keeps at least one move"),
         }
     }
 }
 
+#[derive(Default)]
+pub struct SimpleStrategy {
+    pub food_policy: FoodPolicy,
+}
+
+const STALL_SEARCH_NODES: usize = 50_000;
+
 fn flood_fill_all_directions(
     start: &Coord,
     board_size: (i32, i32),
@@ -97,39 +121,74 @@ fn flood_fill_all_directions(
         .map(|d| (d, start.next_coord_in_dir(d)))
         .filter(|(_, c)| c.x >= 0 && c.y >= 0 && c.x < board_size.0 && c.y < board_size.1)
         .map(|(d, c)| {
-            let reachable_field = flood_fill(
-                c,
-                &blocked_pos,
-                board_size.0,
-                board_size.1,
-            );
+            let reachable_field = flood_fill(c, blocked_pos, board_size.0, board_size.1);
             (*d, reachable_field.len())
         })
         .collect()
 }
 
+/// Subtracts the regions behind chokepoints that an opponent can occupy next turn from the
+/// reachable area of each direction, since they can be sealed off before we get through.
+fn discount_sealable_regions(
+    scores: Vec<(Direction, usize)>,
+    board: &Board,
+    snake: &Battlesnake,
+    blocked_pos: &[Coord],
+) -> Vec<(Direction, usize)> {
+    let opponent_heads = board
+        .snakes
+        .iter()
+        .filter(|s| s.id != snake.id)
+        .map(|s| s.head)
+        .collect::<Vec<_>>();
+    if opponent_heads.is_empty() {
+        return scores;
+    }
+
+    scores
+        .into_iter()
+        .map(|(d, reachable)| {
+            let start = snake.head.next_coord_in_dir(&d);
+            let sealable: usize =
+                analyze_region_from(start, (board.width, board.height), blocked_pos)
+                    .chokepoints
+                    .iter()
+                    .filter(|c| c.cell != start)
+                    .filter(|c| {
+                        opponent_heads
+                            .iter()
+                            .any(|h| (h.x - c.cell.x).abs() + (h.y - c.cell.y).abs() == 1)
+                    })
+                    .map(|c| c.cut_off_size())
+                    .sum();
+            (d, reachable.saturating_sub(sealable))
+        })
+        .collect()
+}
+
 impl Strategy for SimpleStrategy {
-    fn make_move(&self, game: &Game, board: &Board, snake: &Battlesnake) -> Move {
-        let mut possible_moves = all_directions();
-        let goals = &board.food;
+    fn make_move(
+        &self,
+        game: &Game,
+        board: &Board,
+        snake: &Battlesnake,
+        rng: &mut dyn RngCore,
+    ) -> Move {
+        let possible_moves = MoveFilters::legal().allowed(board, snake);
+        let goals = &self.food_policy.goals(board, snake);
         let board_size = (board.width, board.height);
 
         let blocked_pos = board
             .snakes
             .iter()
-            .map(|s| s.body.clone())
-            .flatten()
+            .flat_map(|s| s.body.clone())
             .collect::<Vec<Coord>>();
 
-        self.prevent_self_collision(&mut possible_moves, snake);
-        self.prevent_out_of_bounds(&mut possible_moves, board, snake);
-
-        let flood_fill_scores = flood_fill_all_directions(&snake.head, board_size, &blocked_pos);
-        let dijk_res = dijkstra(
-            snake.head,
-            board_size,
-            &blocked_pos, //&snake.body,
-        );
+        let region_sizes = flood_fill_all_directions(&snake.head, board_size, &blocked_pos);
+        let max_region = region_sizes.iter().map(|(_, v)| *v).max().unwrap_or(usize::MAX);
+        let flood_fill_scores =
+            discount_sealable_regions(region_sizes, board, snake, &blocked_pos);
+        let dijk_res = astar(snake.head, board_size, &blocked_pos, goals);
 
         let max_ff_score = flood_fill_scores
             .iter()
@@ -139,10 +198,18 @@ impl Strategy for SimpleStrategy {
 
         let non_blocking_dirs = flood_fill_scores
             .iter()
-            .filter(|(_, v)| *v == *max_ff_score)
+            .filter(|(d, v)| *v == *max_ff_score && possible_moves.contains(d))
             .map(|(k, _)| k)
             .collect::<Vec<_>>();
 
+        // Every move leads into a region smaller than our body, stall as long as possible.
+        // Regions an opponent might seal only lower the ranking of a move, they don't trap us.
+        if max_region < snake.body.len() {
+            if let Some(dir) = longest_survival_path(board, snake, STALL_SEARCH_NODES).first() {
+                return Move { dir: *dir };
+            }
+        }
+
         let closest_food_dir = dijk_res.get_direction_for_shortest_goal(goals);
 
         if let Some(cfd) = closest_food_dir {
@@ -151,15 +218,170 @@ impl Strategy for SimpleStrategy {
             }
         }
 
+        // Nothing worth eating, circle in open space by following our own tail
+        if closest_food_dir.is_none() {
+            if let Some(dir) = direction_to_own_tail(board, snake) {
+                if non_blocking_dirs.contains(&&dir) {
+                    return Move { dir };
+                }
+            }
+        }
+
         if !non_blocking_dirs.is_empty() {
             return Move {
-                dir: **non_blocking_dirs.choose(&mut rand::rng()).unwrap(),
+                dir: **non_blocking_dirs.choose(rng).unwrap(),
             };
         }
 
         // No legal move found
         Move {
-            dir: Direction::Down,
+            dir: safe_move(board, snake, is_wrapped(game)),
         }
     }
 }
+
+#[derive(Default)]
+pub struct WeightedStrategy {
+    pub weights: EvalWeights,
+}
+
+impl Strategy for WeightedStrategy {
+    fn make_move(
+        &self,
+        game: &Game,
+        board: &Board,
+        snake: &Battlesnake,
+        _: &mut dyn RngCore,
+    ) -> Move {
+        let best = all_directions()
+            .into_iter()
+            .filter_map(|d| evaluate_move(board, snake, &d).map(|t| (d, t.score(&self.weights))))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        match best {
+            Some((dir, _)) => Move { dir },
+            // No legal move found
+            None => Move {
+                dir: safe_move(board, snake, is_wrapped(game)),
+            },
+        }
+    }
+}
+
+/// Plays like the `SimpleStrategy`, but seals opponents into regions smaller than their body
+/// whenever that takes at most `CUTOFF_DEPTH` moves.
+pub struct CutoffStrategy;
+
+const CUTOFF_DEPTH: usize = 3;
+
+impl Strategy for CutoffStrategy {
+    fn make_move(
+        &self,
+        game: &Game,
+        board: &Board,
+        snake: &Battlesnake,
+        rng: &mut dyn RngCore,
+    ) -> Move {
+        match find_cutoff(board, snake, CUTOFF_DEPTH) {
+            Some(plan) => Move { dir: plan.moves[0] },
+            None => SimpleStrategy::default().make_move(game, board, snake, rng),
+        }
+    }
+}
+
+/// Plays like the `SimpleStrategy`, but switches to an exact search once only one opponent
+/// is left and the board is (nearly) partitioned between the two snakes.
+pub struct DuelStrategy;
+
+const DUEL_MAX_DEPTH: usize = 20;
+const DUEL_MAX_NODES: usize = 100_000;
+
+impl Strategy for DuelStrategy {
+    fn make_move(
+        &self,
+        game: &Game,
+        board: &Board,
+        snake: &Battlesnake,
+        rng: &mut dyn RngCore,
+    ) -> Move {
+        self.make_move_until(game, board, snake, rng, &Deadline::never())
+    }
+
+    fn make_move_until(
+        &self,
+        game: &Game,
+        board: &Board,
+        snake: &Battlesnake,
+        rng: &mut dyn RngCore,
+        deadline: &Deadline,
+    ) -> Move {
+        let fallback = SimpleStrategy::default().make_move(game, board, snake, rng);
+        if !is_duel_endgame(board) {
+            return fallback;
+        }
+        let Some(result) = solve_duel(board, snake, DUEL_MAX_DEPTH, DUEL_MAX_NODES, deadline)
+        else {
+            return fallback;
+        };
+
+        // Keep the heuristic move unless the search proves a better one
+        let best = result.best();
+        if result.outcome_of(fallback.dir) == best {
+            return fallback;
+        }
+        match result.moves.iter().find(|(_, o)| Some(*o) == best) {
+            Some((dir, _)) => Move { dir: *dir },
+            None => fallback,
+        }
+    }
+}
+
+/// Monte Carlo tree search over the moves of all snakes. The tree of every game is kept
+/// between turns and the search continues below the position that was actually reached.
+#[derive(Default)]
+pub struct MctsStrategy {
+    trees: TreeCache,
+}
+
+const MCTS_ITERATIONS: usize = 20_000;
+
+fn mcts_key(game: &Game, snake: &Battlesnake) -> String {
+    format!("{}/{}", game.id, snake.id)
+}
+
+impl Strategy for MctsStrategy {
+    fn make_move(
+        &self,
+        game: &Game,
+        board: &Board,
+        snake: &Battlesnake,
+        rng: &mut dyn RngCore,
+    ) -> Move {
+        self.make_move_until(game, board, snake, rng, &Deadline::never())
+    }
+
+    fn make_move_until(
+        &self,
+        game: &Game,
+        board: &Board,
+        snake: &Battlesnake,
+        rng: &mut dyn RngCore,
+        deadline: &Deadline,
+    ) -> Move {
+        let key = mcts_key(game, snake);
+        let (mut tree, search) = self.trees.take(&key, board);
+        tree.search(MCTS_ITERATIONS, deadline, rng);
+        let best = tree.best_move(&snake.id);
+        self.trees.keep(&key, tree, search);
+
+        match best {
+            Some((dir, visits)) if visits > 0 => Move { dir },
+            // Nothing to search without opponents
+            _ => SimpleStrategy::default().make_move(game, board, snake, rng),
+        }
+    }
+
+    fn game_over(&self, game: &Game, snake: &Battlesnake) {
+        self.trees.remove(&mcts_key(game, snake));
+    }
+}
@@ -0,0 +1,244 @@
+use crate::game::{all_directions, Battlesnake, Board, Coord, Direction};
+
+const MAX_HEALTH: i32 = 100;
+
+/// Searches for the longest self-avoiding walk of `snake`, used to stall as long as possible
+/// when it is trapped. Body cells count as free again once the tail has moved past them.
+/// The search stops after `max_nodes` expanded nodes and returns the best walk found so far.
+pub fn longest_survival_path(
+    board: &Board,
+    snake: &Battlesnake,
+    max_nodes: usize,
+) -> Vec<Direction> {
+    let mut search = StallSearch::new(board, snake, max_nodes);
+    search.dfs(snake.head, 1, snake.health, snake.body.len());
+    search.best
+}
+
+struct StallSearch {
+    width: i32,
+    height: i32,
+    /// First step in which the cell can be entered
+    free_at: Vec<usize>,
+    /// For cells of our own body, how often we had eaten when the cell was entered. Each
+    /// food eaten since then keeps the cell occupied one step longer.
+    own_growth: Vec<Option<usize>>,
+    food: Vec<bool>,
+    path: Vec<Direction>,
+    best: Vec<Direction>,
+    growth: usize,
+    nodes: usize,
+    max_nodes: usize,
+}
+
+impl StallSearch {
+    fn new(board: &Board, snake: &Battlesnake, max_nodes: usize) -> StallSearch {
+        let n = (board.width * board.height) as usize;
+        let mut search = StallSearch {
+            width: board.width,
+            height: board.height,
+            free_at: vec![0; n],
+            own_growth: vec![None; n],
+            food: vec![false; n],
+            path: vec![],
+            best: vec![],
+            growth: 0,
+            nodes: 0,
+            max_nodes,
+        };
+
+        for s in &board.snakes {
+            let len = s.body.len();
+            for (i, c) in s.body.iter().enumerate() {
+                if let Some(idx) = search.idx(c) {
+                    search.free_at[idx] = search.free_at[idx].max(len - i);
+                    if s.id == snake.id {
+                        search.own_growth[idx] = Some(0);
+                    }
+                }
+            }
+        }
+        for f in &board.food {
+            if let Some(idx) = search.idx(f) {
+                search.food[idx] = true;
+            }
+        }
+        search
+    }
+
+    fn idx(&self, c: &Coord) -> Option<usize> {
+        if c.x >= 0 && c.y >= 0 && c.x < self.width && c.y < self.height {
+            Some((c.y * self.width + c.x) as usize)
+        } else {
+            None
+        }
+    }
+
+    fn enterable(&self, idx: usize, step: usize) -> bool {
+        let delay = self.own_growth[idx].map_or(0, |g| self.growth - g);
+        step >= self.free_at[idx] + delay
+    }
+
+    fn free_neighbors(&self, c: &Coord, step: usize) -> usize {
+        all_directions()
+            .iter()
+            .filter_map(|d| self.idx(&c.next_coord_in_dir(d)))
+            .filter(|i| self.enterable(*i, step))
+            .count()
+    }
+
+    fn dfs(&mut self, pos: Coord, step: usize, health: i32, length: usize) {
+        if self.path.len() > self.best.len() {
+            self.best = self.path.clone();
+        }
+        self.nodes += 1;
+        if self.nodes >= self.max_nodes {
+            return;
+        }
+
+        // Hugging walls and bodies first finds long walks much earlier
+        let mut candidates = all_directions()
+            .into_iter()
+            .filter_map(|d| {
+                let next = pos.next_coord_in_dir(&d);
+                let idx = self.idx(&next)?;
+                self.enterable(idx, step)
+                    .then(|| (d, next, idx, self.free_neighbors(&next, step + 1)))
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|c| c.3);
+
+        for (dir, next, idx, _) in candidates {
+            let eats = self.food[idx];
+            let (next_health, next_length) = if eats {
+                (MAX_HEALTH, length + 1)
+            } else {
+                (health - 1, length)
+            };
+            if next_health <= 0 {
+                continue;
+            }
+
+            let prev_free_at = self.free_at[idx];
+            let prev_own = self.own_growth[idx];
+            if eats {
+                self.growth += 1;
+            }
+            self.free_at[idx] = step + next_length;
+            self.own_growth[idx] = Some(self.growth);
+            self.food[idx] = false;
+            self.path.push(dir);
+
+            self.dfs(next, step + 1, next_health, next_length);
+
+            self.path.pop();
+            if eats {
+                self.growth -= 1;
+            }
+            self.food[idx] = eats;
+            self.own_growth[idx] = prev_own;
+            self.free_at[idx] = prev_free_at;
+
+            if self.nodes >= self.max_nodes {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::{step, Ruleset};
+    use crate::test_support::{board, snake, snake_with_health};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_fills_open_region() {
+        // 3x3 board, snake of length 2 in the corner can visit every free cell
+        let me = snake("me", vec![Coord { x: 0, y: 0 }, Coord { x: 0, y: 1 }]);
+        let b = board(3, 3, vec![me.clone()]);
+        let path = longest_survival_path(&b, &me, 100_000);
+
+        // Following its own tail the snake never runs out of space
+        assert!(path.len() >= 7);
+    }
+
+    #[test]
+    fn test_tail_cells_become_free() {
+        // 2x2 board completely filled by the snake, only the tail cell frees up
+        let me = snake(
+            "me",
+            vec![
+                Coord { x: 1, y: 0 },
+                Coord { x: 1, y: 1 },
+                Coord { x: 0, y: 1 },
+                Coord { x: 0, y: 0 },
+            ],
+        );
+        let b = board(2, 2, vec![me.clone()]);
+        let path = longest_survival_path(&b, &me, 10_000);
+
+        // Circling in a 2x2 box works forever, the search is capped by health and nodes
+        assert!(path.len() > 10);
+        assert_eq!(path[0], Direction::Left);
+    }
+
+    #[test]
+    fn test_stops_at_dead_end() {
+        // 7x1 line, the body blocks everything on the right for longer than the walk lasts
+        let me = snake(
+            "me",
+            vec![
+                Coord { x: 3, y: 0 },
+                Coord { x: 4, y: 0 },
+                Coord { x: 5, y: 0 },
+            ],
+        );
+        let b = board(7, 1, vec![me.clone()]);
+        let path = longest_survival_path(&b, &me, 10_000);
+
+        assert_eq!(path, vec![Direction::Left; 3]);
+    }
+
+    #[test]
+    fn test_path_cells_stay_occupied_longer_after_eating() {
+        // 2x4 board, we fill the bottom half and walk a loop through the top half that has
+        // food in its far corner
+        let me = snake_with_health(
+            "me",
+            vec![
+                Coord { x: 0, y: 1 },
+                Coord { x: 0, y: 0 },
+                Coord { x: 1, y: 0 },
+                Coord { x: 1, y: 1 },
+            ],
+            10,
+        );
+        let mut b = board(2, 4, vec![me.clone()]);
+        b.food = vec![Coord { x: 1, y: 3 }];
+        let path = longest_survival_path(&b, &me, 10_000);
+        assert!(path.len() > 10);
+
+        // Every step of the walk is survived under the rules
+        let ruleset = Ruleset {
+            hazard_damage_per_turn: 0,
+            ..Ruleset::no_spawn()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        for dir in path {
+            let moves = HashMap::from([("me".to_string(), dir)]);
+            assert!(step(&mut b, &moves, &ruleset, &mut rng).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_limited_by_health() {
+        let me = snake_with_health("me", vec![Coord { x: 0, y: 0 }, Coord { x: 0, y: 1 }], 3);
+        let b = board(5, 5, vec![me.clone()]);
+        // The third move would starve the snake
+        assert_eq!(longest_survival_path(&b, &me, 10_000).len(), 2);
+    }
+}
@@ -0,0 +1,39 @@
+use crate::game::{Battlesnake, Board, Coord};
+
+/// Snake with full health, its head is the first body part
+pub fn snake(id: &str, body: Vec<Coord>) -> Battlesnake {
+    snake_with_health(id, body, 100)
+}
+
+pub fn snake_with_health(id: &str, body: Vec<Coord>, health: i32) -> Battlesnake {
+    Battlesnake {
+        id: id.to_string(),
+        name: id.to_string(),
+        health,
+        head: body[0],
+        length: body.len() as i32,
+        body,
+        latency: "0".to_string(),
+        shout: None,
+    }
+}
+
+/// Board without food and hazards
+pub fn board(width: i32, height: i32, snakes: Vec<Battlesnake>) -> Board {
+    board_with_food(width, height, snakes, vec![])
+}
+
+pub fn board_with_food(
+    width: i32,
+    height: i32,
+    snakes: Vec<Battlesnake>,
+    food: Vec<Coord>,
+) -> Board {
+    Board {
+        height,
+        width,
+        food,
+        snakes,
+        hazards: vec![],
+    }
+}
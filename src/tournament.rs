@@ -0,0 +1,575 @@
+use crate::rules::{DeathCause, Ruleset};
+use crate::simulation::{play_game, GameRecord, SimulationSettings};
+use crate::strategy::{build_strategy, Strategy, STRATEGY_NAMES};
+use crate::utils::{parse_board_size, parse_num};
+use rand::{rng, Rng};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_RATING: f64 = 1500.0;
+const ELO_K: f64 = 32.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+    RoundRobin,
+    Swiss { rounds: usize },
+}
+
+#[derive(Debug, Clone)]
+pub struct TournamentConfig {
+    pub players_per_game: usize,
+    pub schedule: Schedule,
+    pub games_per_table: usize,
+    pub settings: SimulationSettings,
+    pub ratings_path: PathBuf,
+    pub entrants: Vec<String>,
+    /// Base seed of all games, a random one is chosen if not given
+    pub seed: Option<u64>,
+}
+
+impl Default for TournamentConfig {
+    fn default() -> Self {
+        TournamentConfig {
+            players_per_game: 2,
+            schedule: Schedule::RoundRobin,
+            games_per_table: 10,
+            settings: SimulationSettings {
+                width: 11,
+                height: 11,
+                max_turns: 1000,
+                ruleset: Ruleset::default(),
+            },
+            ratings_path: PathBuf::from("logs/ratings.json"),
+            entrants: STRATEGY_NAMES.iter().map(|s| s.to_string()).collect(),
+            seed: None,
+        }
+    }
+}
+
+impl TournamentConfig {
+    /// Parses `[--players 2|4] [--swiss ROUNDS] [--games N] [--size WxH] [--max-turns N]
+    /// [--ratings PATH] [--seed N] [STRATEGY...]`
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut config = TournamentConfig::default();
+        let mut entrants = Vec::new();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--players" => config.players_per_game = parse_num(value()?)?,
+                "--swiss" => {
+                    config.schedule = Schedule::Swiss {
+                        rounds: parse_num(value()?)?,
+                    }
+                }
+                "--games" => config.games_per_table = parse_num(value()?)?,
+                "--max-turns" => config.settings.max_turns = parse_num(value()?)?,
+                "--ratings" => config.ratings_path = PathBuf::from(value()?),
+                "--seed" => config.seed = Some(parse_num(value()?)?),
+                "--size" => {
+                    (config.settings.width, config.settings.height) = parse_board_size(value()?)?
+                }
+                name => entrants.push(name.to_string()),
+            }
+        }
+
+        if !entrants.is_empty() {
+            config.entrants = entrants;
+        }
+        // Ratings are keyed by the spec, two entrants with the same one would share a rating
+        if let Some(dup) = config
+            .entrants
+            .iter()
+            .enumerate()
+            .find_map(|(i, e)| config.entrants[..i].contains(e).then_some(e))
+        {
+            return Err(format!("Entrant {} is listed more than once", dup));
+        }
+        if config.players_per_game < 2 {
+            return Err("A tournament game needs at least two players".to_string());
+        }
+        config.settings.check_players(config.players_per_game)?;
+        if config.entrants.len() < config.players_per_game {
+            return Err(format!(
+                "Need at least {} entrants, got {}",
+                config.players_per_game,
+                config.entrants.len()
+            ));
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Rating {
+    pub rating: f64,
+    pub games: u32,
+    pub wins: u32,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            rating: DEFAULT_RATING,
+            games: 0,
+            wins: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Ratings {
+    pub players: BTreeMap<String, Rating>,
+}
+
+impl Ratings {
+    /// Ratings stored at `path`, empty if the file does not exist yet
+    pub fn load(path: &Path) -> Result<Ratings, String> {
+        match fs::read_to_string(path) {
+            Ok(s) => serde_json::from_str(&s)
+                .map_err(|e| format!("Invalid ratings file {}: {}", path.display(), e)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Ratings::default()),
+            Err(e) => Err(format!("Could not read {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn rating(&self, name: &str) -> f64 {
+        self.players
+            .get(name)
+            .map(|r| r.rating)
+            .unwrap_or(DEFAULT_RATING)
+    }
+
+    /// Multiplayer Elo: every game is split into pairwise duels between all participants,
+    /// the K factor is divided by the number of opponents.
+    pub fn update(&mut self, results: &[(&str, usize)]) {
+        let n = results.len();
+        if n < 2 {
+            return;
+        }
+        let k = ELO_K / (n - 1) as f64;
+        let mut deltas = vec![0.0; n];
+
+        for i in 0..n {
+            for j in (i + 1)..n {
+                let (ri, rj) = (self.rating(results[i].0), self.rating(results[j].0));
+                let expected_i = 1.0 / (1.0 + 10f64.powf((rj - ri) / 400.0));
+                let score_i = match results[i].1.cmp(&results[j].1) {
+                    std::cmp::Ordering::Less => 1.0,
+                    std::cmp::Ordering::Equal => 0.5,
+                    std::cmp::Ordering::Greater => 0.0,
+                };
+                deltas[i] += k * (score_i - expected_i);
+                deltas[j] -= k * (score_i - expected_i);
+            }
+        }
+
+        for ((name, placement), delta) in results.iter().zip(deltas) {
+            let entry = self.players.entry(name.to_string()).or_default();
+            entry.rating += delta;
+            entry.games += 1;
+            if *placement == 1 {
+                entry.wins += 1;
+            }
+        }
+    }
+}
+
+/// Number of eliminations per strategy and cause. Collisions are labeled with the strategy
+/// of the other snake, e.g. `head-to-head (simple)`.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(transparent)]
+pub struct DeathCauseStats {
+    pub per_strategy: BTreeMap<String, BTreeMap<String, u32>>,
+}
+
+impl DeathCauseStats {
+    pub fn record(&mut self, record: &GameRecord, names: &[&str]) {
+        for (name, cause) in names.iter().zip(&record.causes) {
+            let label = match cause {
+                Some(cause) => cause_label(cause, record, names),
+                None => "survived".to_string(),
+            };
+            *self
+                .per_strategy
+                .entry(name.to_string())
+                .or_default()
+                .entry(label)
+                .or_default() += 1;
+        }
+    }
+}
+
+fn cause_label(cause: &DeathCause, record: &GameRecord, names: &[&str]) -> String {
+    let other = cause
+        .other_snake()
+        .and_then(|id| record.snake_ids.iter().position(|i| i == id));
+    match other {
+        Some(idx) => format!("{} ({})", cause.kind(), names[idx]),
+        None => cause.kind().to_string(),
+    }
+}
+
+pub struct TournamentSummary {
+    pub ratings: Ratings,
+    pub death_causes: DeathCauseStats,
+}
+
+fn combinations(n: usize, k: usize) -> Vec<Vec<usize>> {
+    if k == 0 {
+        return vec![vec![]];
+    }
+    if n < k {
+        return vec![];
+    }
+    let mut result = combinations(n - 1, k);
+    for mut c in combinations(n - 1, k - 1) {
+        c.push(n - 1);
+        result.push(c);
+    }
+    result
+}
+
+/// Pairing state of a Swiss tournament. Entrants with the same score meet, rematches are
+/// avoided where possible and the entrants that do not fit on a table get a bye in turn.
+#[derive(Debug, Clone)]
+struct SwissPairing {
+    // One point per opponent placed behind, half a point per tie
+    scores: Vec<f64>,
+    byes: Vec<u32>,
+    met: HashSet<(usize, usize)>,
+}
+
+impl SwissPairing {
+    fn new(entrants: usize) -> SwissPairing {
+        SwissPairing {
+            scores: vec![0.0; entrants],
+            byes: vec![0; entrants],
+            met: HashSet::new(),
+        }
+    }
+
+    fn has_met(&self, a: usize, b: usize) -> bool {
+        self.met.contains(&(a.min(b), a.max(b)))
+    }
+
+    /// Tables of the next round, `ratings` break ties within a score group
+    fn tables(&mut self, ratings: &[f64], players: usize) -> Vec<Vec<usize>> {
+        let mut order = (0..self.scores.len()).collect::<Vec<_>>();
+        order.sort_by(|a, b| {
+            self.scores[*b]
+                .total_cmp(&self.scores[*a])
+                .then(ratings[*b].total_cmp(&ratings[*a]))
+        });
+
+        // The lowest ranked of those with the fewest byes sit out, a bye counts as a draw
+        let mut sitting_out = order.iter().rev().copied().collect::<Vec<_>>();
+        sitting_out.sort_by_key(|i| self.byes[*i]);
+        for i in sitting_out.into_iter().take(order.len() % players) {
+            self.byes[i] += 1;
+            self.scores[i] += (players - 1) as f64 / 2.0;
+            order.retain(|j| *j != i);
+        }
+
+        let mut tables = vec![];
+        while !order.is_empty() {
+            let mut table = vec![order.remove(0)];
+            while table.len() < players {
+                // The next in the standings that met the fewest of the table before
+                let pick = (0..order.len())
+                    .min_by_key(|k| {
+                        table
+                            .iter()
+                            .filter(|t| self.has_met(**t, order[*k]))
+                            .count()
+                    })
+                    .unwrap_or(0);
+                table.push(order.remove(pick));
+            }
+            tables.push(table);
+        }
+        tables
+    }
+
+    fn record(&mut self, table: &[usize], placements: &[usize]) {
+        for (i, a) in table.iter().enumerate() {
+            for (j, b) in table.iter().enumerate().skip(i + 1) {
+                self.met.insert((*a.min(b), *a.max(b)));
+                let (score_a, score_b) = match placements[i].cmp(&placements[j]) {
+                    std::cmp::Ordering::Less => (1.0, 0.0),
+                    std::cmp::Ordering::Equal => (0.5, 0.5),
+                    std::cmp::Ordering::Greater => (0.0, 1.0),
+                };
+                self.scores[*a] += score_a;
+                self.scores[*b] += score_b;
+            }
+        }
+    }
+}
+
+pub fn run(config: &TournamentConfig) -> Result<TournamentSummary, String> {
+    let strategies = config
+        .entrants
+        .iter()
+        .map(|name| build_strategy(name))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut ratings = Ratings::load(&config.ratings_path)?;
+    let mut death_causes = DeathCauseStats::default();
+    let seed = config.seed.unwrap_or_else(|| rng().random());
+    println!("seed: {}", seed);
+
+    let rounds = match config.schedule {
+        Schedule::RoundRobin => 1,
+        Schedule::Swiss { rounds } => rounds,
+    };
+
+    let mut swiss = SwissPairing::new(config.entrants.len());
+    let mut game_nr = 0;
+    for round in 0..rounds {
+        let tables = match config.schedule {
+            Schedule::RoundRobin => combinations(config.entrants.len(), config.players_per_game),
+            Schedule::Swiss { .. } => {
+                let current = config
+                    .entrants
+                    .iter()
+                    .map(|e| ratings.rating(e))
+                    .collect::<Vec<_>>();
+                swiss.tables(&current, config.players_per_game)
+            }
+        };
+
+        for table in tables {
+            for _ in 0..config.games_per_table {
+                let participants = table
+                    .iter()
+                    .map(|i| {
+                        (
+                            config.entrants[*i].as_str(),
+                            strategies[*i].as_ref() as &dyn Strategy,
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                game_nr += 1;
+                let record = play_game(
+                    &format!("tournament-{}", game_nr),
+                    &participants,
+                    &config.settings,
+                    seed,
+                );
+
+                let results = participants
+                    .iter()
+                    .zip(&record.placements)
+                    .map(|((name, _), p)| (*name, *p))
+                    .collect::<Vec<_>>();
+                ratings.update(&results);
+                swiss.record(&table, &record.placements);
+                death_causes.record(&record, &results.iter().map(|r| r.0).collect::<Vec<_>>());
+
+                println!(
+                    "round {} game {}: {:?} after {} turns",
+                    round + 1,
+                    game_nr,
+                    results,
+                    record.turns
+                );
+            }
+        }
+    }
+
+    ratings
+        .save(&config.ratings_path)
+        .map_err(|e| format!("Could not save ratings: {}", e))?;
+    Ok(TournamentSummary {
+        ratings,
+        death_causes,
+    })
+}
+
+pub fn print_standings(ratings: &Ratings) {
+    let mut standings = ratings.players.iter().collect::<Vec<_>>();
+    standings.sort_by(|a, b| b.1.rating.total_cmp(&a.1.rating));
+
+    println!(
+        "{:<20} {:>8} {:>6} {:>6}",
+        "strategy", "rating", "games", "wins"
+    );
+    for (name, r) in standings {
+        println!(
+            "{:<20} {:>8.1} {:>6} {:>6}",
+            name, r.rating, r.games, r.wins
+        );
+    }
+}
+
+pub fn print_death_causes(stats: &DeathCauseStats) {
+    for (name, causes) in &stats.per_strategy {
+        println!("{}", name);
+        for (cause, count) in causes {
+            println!("    {:<32} {:>6}", cause, count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duel_update_is_zero_sum() {
+        let mut ratings = Ratings::default();
+        ratings.update(&[("a", 1), ("b", 2)]);
+
+        assert_eq!(ratings.rating("a"), DEFAULT_RATING + ELO_K / 2.0);
+        assert_eq!(ratings.rating("b"), DEFAULT_RATING - ELO_K / 2.0);
+        assert_eq!(ratings.players["a"].wins, 1);
+        assert_eq!(ratings.players["b"].games, 1);
+    }
+
+    #[test]
+    fn test_draw_between_equal_ratings_changes_nothing() {
+        let mut ratings = Ratings::default();
+        ratings.update(&[("a", 1), ("b", 1)]);
+
+        assert_eq!(ratings.rating("a"), DEFAULT_RATING);
+        assert_eq!(ratings.rating("b"), DEFAULT_RATING);
+    }
+
+    #[test]
+    fn test_four_player_update_orders_by_placement() {
+        let mut ratings = Ratings::default();
+        ratings.update(&[("a", 3), ("b", 1), ("c", 4), ("d", 2)]);
+
+        assert!(ratings.rating("b") > ratings.rating("d"));
+        assert!(ratings.rating("d") > ratings.rating("a"));
+        assert!(ratings.rating("a") > ratings.rating("c"));
+        let total: f64 = ratings.players.values().map(|r| r.rating).sum();
+        assert!((total - 4.0 * DEFAULT_RATING).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_load_ratings() {
+        let dir = std::env::temp_dir().join(format!("ratings-test-{}", std::process::id()));
+        let path = dir.join("ratings.json");
+        assert!(Ratings::load(&path).unwrap().players.is_empty());
+
+        let mut ratings = Ratings::default();
+        ratings.update(&[("a", 1), ("b", 2)]);
+        ratings.save(&path).unwrap();
+        assert_eq!(
+            Ratings::load(&path).unwrap().rating("a"),
+            ratings.rating("a")
+        );
+
+        // A corrupt file is reported instead of being replaced by empty ratings
+        fs::write(&path, "{\"a\": ").unwrap();
+        assert!(Ratings::load(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_death_cause_stats_label_other_strategy() {
+        let record = GameRecord {
+            turns: 10,
+            placements: vec![2, 1],
+            causes: vec![Some(DeathCause::HeadToHead("snake-1".to_string())), None],
+            snake_ids: vec!["snake-0".to_string(), "snake-1".to_string()],
+        };
+        let mut stats = DeathCauseStats::default();
+        stats.record(&record, &["random", "simple"]);
+        stats.record(&record, &["random", "simple"]);
+
+        assert_eq!(stats.per_strategy["random"]["head-to-head (simple)"], 2);
+        assert_eq!(stats.per_strategy["simple"]["survived"], 2);
+    }
+
+    #[test]
+    fn test_combinations() {
+        assert_eq!(combinations(3, 2).len(), 3);
+        assert_eq!(combinations(5, 4).len(), 5);
+        assert!(combinations(2, 4).is_empty());
+    }
+
+    #[test]
+    fn test_swiss_pairs_score_groups_without_rematches() {
+        let mut swiss = SwissPairing::new(4);
+        let ratings = [1500.0, 1500.0, 1600.0, 1500.0];
+
+        let tables = swiss.tables(&ratings, 2);
+        assert_eq!(tables, vec![vec![2, 0], vec![1, 3]]);
+        swiss.record(&tables[0], &[1, 2]);
+        swiss.record(&tables[1], &[1, 2]);
+
+        // The winners meet, then everyone plays the one opponent left
+        let tables = swiss.tables(&ratings, 2);
+        assert_eq!(tables, vec![vec![2, 1], vec![0, 3]]);
+        swiss.record(&tables[0], &[1, 2]);
+        swiss.record(&tables[1], &[1, 2]);
+        assert_eq!(swiss.tables(&ratings, 2), vec![vec![2, 3], vec![0, 1]]);
+    }
+
+    #[test]
+    fn test_swiss_byes_rotate() {
+        let mut swiss = SwissPairing::new(5);
+        let ratings = [1500.0; 5];
+        for _ in 0..5 {
+            let tables = swiss.tables(&ratings, 2);
+            assert_eq!(tables.len(), 2);
+            for table in &tables {
+                swiss.record(table, &[1, 2]);
+            }
+        }
+        assert_eq!(swiss.byes, vec![1; 5]);
+    }
+
+    #[test]
+    fn test_from_args() {
+        let args = [
+            "--players",
+            "4",
+            "--swiss",
+            "3",
+            "--size",
+            "7x9",
+            "a",
+            "b",
+            "c",
+            "d",
+        ]
+        .map(String::from);
+        let config = TournamentConfig::from_args(&args).unwrap();
+
+        assert_eq!(config.players_per_game, 4);
+        assert_eq!(config.schedule, Schedule::Swiss { rounds: 3 });
+        assert_eq!((config.settings.width, config.settings.height), (7, 9));
+        assert_eq!(config.entrants.len(), 4);
+        assert!(TournamentConfig::from_args(&["--players".to_string(), "8".to_string()]).is_err());
+        assert!(TournamentConfig::from_args(&["simple", "simple"].map(String::from)).is_err());
+
+        // Every player needs a starting position
+        let too_small = ["--size", "2x11"].map(String::from);
+        assert!(TournamentConfig::from_args(&too_small).is_err());
+        let mut crowded = vec!["--players".to_string(), "9".to_string()];
+        crowded.extend((0..9).map(|i| format!("s{}", i)));
+        assert!(TournamentConfig::from_args(&crowded).is_err());
+        let crowded = ["--players", "4", "--size", "3x3", "a", "b", "c", "d"];
+        assert!(TournamentConfig::from_args(&crowded.map(String::from)).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+/// Fixed-size hash table of search results keyed by Zobrist hash. Each key maps to a single
+/// slot; a colliding entry is replaced if it stems from an earlier search or was searched
+/// less deep than the new one.
+#[derive(Debug, Clone)]
+pub struct TranspositionTable<V> {
+    entries: Vec<Option<Entry<V>>>,
+    mask: usize,
+    generation: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry<V> {
+    key: u64,
+    depth: usize,
+    generation: u32,
+    value: V,
+}
+
+impl<V: Copy> TranspositionTable<V> {
+    /// Table with at least `capacity` slots, rounded up to a power of two
+    pub fn new(capacity: usize) -> TranspositionTable<V> {
+        let size = capacity.max(1).next_power_of_two();
+        TranspositionTable {
+            entries: vec![None; size],
+            mask: size - 1,
+            generation: 0,
+        }
+    }
+
+    /// Marks all current entries as old, so they are replaced first
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// The stored depth and value for `key`
+    pub fn probe(&self, key: u64) -> Option<(usize, V)> {
+        self.entries[key as usize & self.mask]
+            .filter(|e| e.key == key)
+            .map(|e| (e.depth, e.value))
+    }
+
+    pub fn store(&mut self, key: u64, depth: usize, value: V) {
+        let generation = self.generation;
+        let slot = &mut self.entries[key as usize & self.mask];
+        let replace = match slot {
+            None => true,
+            Some(e) => e.key == key || e.generation != generation || depth >= e.depth,
+        };
+        if replace {
+            *slot = Some(Entry {
+                key,
+                depth,
+                generation,
+                value,
+            });
+        }
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries.iter().filter(|e| e.is_some()).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_and_probe() {
+        let mut table = TranspositionTable::new(10);
+        assert_eq!(table.entries.len(), 16);
+
+        table.store(3, 2, 'a');
+        assert_eq!(table.probe(3), Some((2, 'a')));
+        // Same slot, different key
+        assert_eq!(table.probe(3 + 16), None);
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn test_deeper_entries_are_kept() {
+        let mut table = TranspositionTable::new(16);
+        table.store(1, 5, 'a');
+        table.store(17, 3, 'b');
+        assert_eq!(table.probe(1), Some((5, 'a')));
+        assert_eq!(table.probe(17), None);
+
+        table.store(17, 6, 'c');
+        assert_eq!(table.probe(17), Some((6, 'c')));
+
+        // Results for the same position always replace the old one
+        table.store(17, 1, 'd');
+        assert_eq!(table.probe(17), Some((1, 'd')));
+    }
+
+    #[test]
+    fn test_old_entries_are_replaced() {
+        let mut table = TranspositionTable::new(16);
+        table.store(1, 5, 'a');
+        table.new_search();
+        table.store(17, 1, 'b');
+        assert_eq!(table.probe(17), Some((1, 'b')));
+    }
+}
@@ -0,0 +1,282 @@
+use crate::evaluation::EvalWeights;
+use crate::rules::Ruleset;
+use crate::simulation::{play_game, SimulationSettings};
+use crate::strategy::{Strategy, WeightedStrategy};
+use crate::utils::{parse_board_size, parse_num};
+use rand::rngs::StdRng;
+use rand::{rng, Rng, SeedableRng};
+use std::path::PathBuf;
+
+/// Settings for tuning the evaluation weights with SPSA
+/// (simultaneous perturbation stochastic approximation) on local self-play games.
+#[derive(Debug, Clone)]
+pub struct TunerConfig {
+    pub iterations: usize,
+    pub games_per_batch: usize,
+    /// Initial step size of the parameter update
+    pub learning_rate: f64,
+    /// Initial size of the random perturbation
+    pub perturbation: f64,
+    pub settings: SimulationSettings,
+    pub init_path: Option<PathBuf>,
+    pub out_path: PathBuf,
+    /// Seed for the perturbations and games, a random one is chosen if not given
+    pub seed: Option<u64>,
+}
+
+impl Default for TunerConfig {
+    fn default() -> Self {
+        TunerConfig {
+            iterations: 50,
+            games_per_batch: 20,
+            learning_rate: 0.1,
+            perturbation: 0.2,
+            settings: SimulationSettings {
+                width: 11,
+                height: 11,
+                max_turns: 500,
+                ruleset: Ruleset::default(),
+            },
+            init_path: None,
+            out_path: PathBuf::from("weights.json"),
+            seed: None,
+        }
+    }
+}
+
+impl TunerConfig {
+    /// Parses `[--iterations N] [--games N] [--size WxH] [--init PATH] [--out PATH] [--seed N]`
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut config = TunerConfig::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--iterations" => config.iterations = parse_num(value()?)?,
+                "--games" => config.games_per_batch = parse_num(value()?)?,
+                "--init" => config.init_path = Some(PathBuf::from(value()?)),
+                "--out" => config.out_path = PathBuf::from(value()?),
+                "--seed" => config.seed = Some(parse_num(value()?)?),
+                "--size" => {
+                    (config.settings.width, config.settings.height) = parse_board_size(value()?)?
+                }
+                other => return Err(format!("Unknown argument {}", other)),
+            }
+        }
+        // The weights are only written after an iteration
+        if config.iterations == 0 {
+            return Err("Tuning needs at least one iteration".to_string());
+        }
+        config.settings.check_players(2)?;
+        Ok(config)
+    }
+}
+
+/// Plays a batch of duels between two weight vectors and returns the score of `a`
+/// in [-1, 1] (1 = all games won, -1 = all games lost).
+fn duel_score(
+    batch_id: &str,
+    a: &EvalWeights,
+    b: &EvalWeights,
+    config: &TunerConfig,
+    seed: u64,
+) -> f64 {
+    let a = WeightedStrategy { weights: a.clone() };
+    let b = WeightedStrategy { weights: b.clone() };
+    let participants: [(&str, &dyn Strategy); 2] = [("a", &a), ("b", &b)];
+
+    let total: f64 = (0..config.games_per_batch)
+        .map(|i| {
+            let record = play_game(
+                &format!("{}-{}", batch_id, i),
+                &participants,
+                &config.settings,
+                seed,
+            );
+            match record.placements[0].cmp(&record.placements[1]) {
+                std::cmp::Ordering::Less => 1.0,
+                std::cmp::Ordering::Equal => 0.0,
+                std::cmp::Ordering::Greater => -1.0,
+            }
+        })
+        .sum();
+    total / config.games_per_batch.max(1) as f64
+}
+
+/// Random ±1 direction for every weight
+fn perturbation_signs(rng: &mut impl Rng, len: usize) -> Vec<f64> {
+    (0..len)
+        .map(|_| if rng.random_bool(0.5) { 1.0 } else { -1.0 })
+        .collect()
+}
+
+/// Moves `theta` along the gradient estimated from the score difference `diff`
+/// between `theta + c_k * delta` and `theta - c_k * delta`.
+fn spsa_step(theta: &mut [f64], delta: &[f64], diff: f64, a_k: f64, c_k: f64) {
+    theta
+        .iter_mut()
+        .zip(delta)
+        .for_each(|(t, d)| *t += a_k * diff / (2.0 * c_k * d));
+}
+
+/// Runs SPSA on the evaluation weights. Every iteration plays the two perturbed weight
+/// vectors against each other, steps along the estimated gradient and validates the result
+/// against the initial weights. The best validated weights are written to `out_path`.
+pub fn tune(config: &TunerConfig) -> Result<EvalWeights, String> {
+    let initial = match &config.init_path {
+        Some(path) => EvalWeights::load(path)?,
+        None => EvalWeights::default(),
+    };
+    let seed = config.seed.unwrap_or_else(|| rng().random());
+    println!("seed: {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut theta = initial.to_vec();
+    let mut best = (f64::MIN, initial.clone());
+
+    for k in 0..config.iterations {
+        // Standard SPSA gain sequences
+        let a_k = config.learning_rate / (k as f64 + 1.0).powf(0.602);
+        let c_k = config.perturbation / (k as f64 + 1.0).powf(0.101);
+
+        let delta = perturbation_signs(&mut rng, theta.len());
+        let plus = theta
+            .iter()
+            .zip(&delta)
+            .map(|(t, d)| t + c_k * d)
+            .collect::<Vec<_>>();
+        let minus = theta
+            .iter()
+            .zip(&delta)
+            .map(|(t, d)| t - c_k * d)
+            .collect::<Vec<_>>();
+
+        // f(plus) - f(minus) is estimated directly by playing plus against minus
+        let diff = duel_score(
+            &format!("tune-{}", k),
+            &EvalWeights::from_vec(&plus),
+            &EvalWeights::from_vec(&minus),
+            config,
+            seed,
+        );
+        spsa_step(&mut theta, &delta, diff, a_k, c_k);
+
+        let candidate = EvalWeights::from_vec(&theta);
+        let score = duel_score(
+            &format!("validate-{}", k),
+            &candidate,
+            &initial,
+            config,
+            seed,
+        );
+        println!(
+            "iteration {}: score vs initial {:+.3} weights {:?}",
+            k + 1,
+            score,
+            candidate
+        );
+
+        if score > best.0 {
+            best = (score, candidate);
+            best.1
+                .save(&config.out_path)
+                .map_err(|e| format!("Could not save weights: {}", e))?;
+        }
+    }
+
+    Ok(best.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_from_args() {
+        let config = TunerConfig::from_args(&args(&[
+            "--iterations",
+            "3",
+            "--games",
+            "4",
+            "--size",
+            "7x9",
+            "--seed",
+            "42",
+            "--out",
+            "tuned.json",
+        ]))
+        .unwrap();
+        assert_eq!(config.iterations, 3);
+        assert_eq!(config.games_per_batch, 4);
+        assert_eq!((config.settings.width, config.settings.height), (7, 9));
+        assert_eq!(config.seed, Some(42));
+        assert_eq!(config.out_path, PathBuf::from("tuned.json"));
+        assert_eq!(config.init_path, None);
+    }
+
+    #[test]
+    fn test_from_args_invalid() {
+        assert!(TunerConfig::from_args(&args(&["--iterations", "0"])).is_err());
+        assert!(TunerConfig::from_args(&args(&["--rounds", "3"])).is_err());
+        assert!(TunerConfig::from_args(&args(&["--size", "7"])).is_err());
+        assert!(TunerConfig::from_args(&args(&["--size", "1x1"])).is_err());
+        assert!(TunerConfig::from_args(&args(&["--games"])).is_err());
+    }
+
+    #[test]
+    fn test_spsa_step_follows_winner() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let delta = perturbation_signs(&mut rng, EvalWeights::LEN);
+        let initial = EvalWeights::default().to_vec();
+
+        // The plus side won every game, so every weight moves towards it
+        let mut theta = initial.clone();
+        spsa_step(&mut theta, &delta, 1.0, 0.1, 0.2);
+        for ((t, i), d) in theta.iter().zip(&initial).zip(&delta) {
+            assert_eq!((t - i).signum(), *d);
+        }
+
+        // The minus side won, so the weights move away from the plus side
+        let mut theta = initial.clone();
+        spsa_step(&mut theta, &delta, -1.0, 0.1, 0.2);
+        for ((t, i), d) in theta.iter().zip(&initial).zip(&delta) {
+            assert_eq!((t - i).signum(), -d);
+        }
+
+        // A draw leaves the weights unchanged
+        let mut theta = initial.clone();
+        spsa_step(&mut theta, &delta, 0.0, 0.1, 0.2);
+        assert_eq!(theta, initial);
+    }
+
+    #[test]
+    fn test_perturbation_signs_seeded() {
+        let a = perturbation_signs(&mut StdRng::seed_from_u64(7), EvalWeights::LEN);
+        let b = perturbation_signs(&mut StdRng::seed_from_u64(7), EvalWeights::LEN);
+        assert_eq!(a, b);
+        assert!(a.iter().all(|d| d.abs() == 1.0));
+    }
+
+    #[test]
+    fn test_weights_round_trip() {
+        let path = std::env::temp_dir().join(format!("tuner-weights-{}.json", std::process::id()));
+        let weights = EvalWeights::from_vec(&[0.5, 1.5, -0.25, 2.0, 0.0, 3.0]);
+        weights.save(&path).unwrap();
+        let loaded = EvalWeights::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap().to_vec(), weights.to_vec());
+    }
+
+    #[test]
+    fn test_load_missing_weights() {
+        let path = std::env::temp_dir().join("tuner-weights-does-not-exist.json");
+        assert!(EvalWeights::load(&path).is_err());
+    }
+}
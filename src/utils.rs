@@ -1,4 +1,5 @@
 use serde_json::{json, Value};
+use std::str::FromStr;
 
 // info is called when you create your Battlesnake on play.battlesnake.com
 // and controls your Battlesnake's appearance
@@ -12,3 +13,16 @@ pub fn info() -> Value {
         "tail": "default",
     })
 }
+
+/// Parses a number given on the command line or in a strategy spec
+pub fn parse_num<T: FromStr>(s: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("Invalid number {}", s))
+}
+
+/// Parses a board size of the form `WxH`
+pub fn parse_board_size(s: &str) -> Result<(i32, i32), String> {
+    let (w, h) = s
+        .split_once('x')
+        .ok_or_else(|| format!("Invalid board size {}", s))?;
+    Ok((parse_num(w)?, parse_num(h)?))
+}
@@ -0,0 +1,167 @@
+use crate::game::{Board, Coord};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Snakes beyond this share the keys of earlier slots, which only costs some extra collisions
+const MAX_SNAKES: usize = 8;
+const MAX_HEALTH: i32 = 100;
+const KEY_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+/// Random keys for Zobrist hashing of boards of one size. Health is hashed in buckets of
+/// `health_bucket` points, so searches that only care about rough health share entries.
+#[derive(Debug, Clone)]
+pub struct ZobristKeys {
+    width: i32,
+    height: i32,
+    health_bucket: i32,
+    body: Vec<Vec<u64>>,
+    head: Vec<Vec<u64>>,
+    health: Vec<Vec<u64>>,
+    food: Vec<u64>,
+    hazard: Vec<u64>,
+}
+
+impl ZobristKeys {
+    pub fn new(width: i32, height: i32, health_bucket: i32) -> ZobristKeys {
+        let mut rng = StdRng::seed_from_u64(KEY_SEED);
+        let cells = (width.max(0) * height.max(0)) as usize;
+        let health_bucket = health_bucket.max(1);
+        let buckets = (MAX_HEALTH / health_bucket + 1) as usize;
+        let mut table = |len: usize| (0..len).map(|_| rng.random()).collect::<Vec<u64>>();
+
+        ZobristKeys {
+            width,
+            height,
+            health_bucket,
+            body: (0..MAX_SNAKES).map(|_| table(cells)).collect(),
+            head: (0..MAX_SNAKES).map(|_| table(cells)).collect(),
+            health: (0..MAX_SNAKES).map(|_| table(buckets)).collect(),
+            food: table(cells),
+            hazard: table(cells),
+        }
+    }
+
+    fn cell(&self, c: &Coord) -> Option<usize> {
+        (c.x >= 0 && c.y >= 0 && c.x < self.width && c.y < self.height)
+            .then(|| (c.y * self.width + c.x) as usize)
+    }
+
+    /// Hash of the snake bodies (in the order of `board.snakes`), their health, food and
+    /// hazards. Body keys are rotated by the segment index, so the order of the segments
+    /// matters and a stacked tail does not cancel itself out.
+    pub fn hash(&self, board: &Board) -> u64 {
+        let mut hash = 0;
+        for (i, snake) in board.snakes.iter().enumerate() {
+            let slot = i % MAX_SNAKES;
+            for (j, c) in snake.body.iter().enumerate() {
+                if let Some(cell) = self.cell(c) {
+                    hash ^= self.body[slot][cell].rotate_left(j as u32 % 64);
+                }
+            }
+            if let Some(cell) = self.cell(&snake.head) {
+                hash ^= self.head[slot][cell];
+            }
+            let bucket = (snake.health.clamp(0, MAX_HEALTH) / self.health_bucket) as usize;
+            hash ^= self.health[slot][bucket];
+        }
+        for c in &board.food {
+            if let Some(cell) = self.cell(c) {
+                hash ^= self.food[cell];
+            }
+        }
+        for c in &board.hazards {
+            if let Some(cell) = self.cell(c) {
+                hash ^= self.hazard[cell];
+            }
+        }
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{board, board_with_food, snake_with_health};
+
+    fn body() -> Vec<Coord> {
+        vec![
+            Coord { x: 1, y: 1 },
+            Coord { x: 1, y: 2 },
+            Coord { x: 2, y: 2 },
+        ]
+    }
+
+    #[test]
+    fn test_same_board_same_hash() {
+        let keys = ZobristKeys::new(5, 5, 1);
+        let a = board_with_food(
+            5,
+            5,
+            vec![snake_with_health("me", body(), 50)],
+            vec![Coord { x: 0, y: 0 }],
+        );
+        assert_eq!(keys.hash(&a), keys.hash(&a.clone()));
+        assert_eq!(keys.hash(&a), ZobristKeys::new(5, 5, 1).hash(&a));
+    }
+
+    #[test]
+    fn test_hash_depends_on_every_component() {
+        let keys = ZobristKeys::new(5, 5, 1);
+        let base = board_with_food(
+            5,
+            5,
+            vec![snake_with_health("me", body(), 50)],
+            vec![Coord { x: 0, y: 0 }],
+        );
+        let hash = keys.hash(&base);
+
+        let mut reversed = body();
+        reversed.reverse();
+        let changed = [
+            board_with_food(
+                5,
+                5,
+                vec![snake_with_health("me", reversed, 50)],
+                vec![Coord { x: 0, y: 0 }],
+            ),
+            board_with_food(
+                5,
+                5,
+                vec![snake_with_health("me", body(), 49)],
+                vec![Coord { x: 0, y: 0 }],
+            ),
+            board_with_food(
+                5,
+                5,
+                vec![snake_with_health("me", body(), 50)],
+                vec![Coord { x: 4, y: 0 }],
+            ),
+            Board {
+                hazards: vec![Coord { x: 3, y: 3 }],
+                ..base.clone()
+            },
+        ];
+        for b in &changed {
+            assert_ne!(keys.hash(b), hash);
+        }
+    }
+
+    #[test]
+    fn test_stacked_tail_is_hashed() {
+        let keys = ZobristKeys::new(5, 5, 1);
+        let mut grown = body();
+        grown.push(Coord { x: 2, y: 2 });
+        assert_ne!(
+            keys.hash(&board(5, 5, vec![snake_with_health("me", body(), 50)])),
+            keys.hash(&board(5, 5, vec![snake_with_health("me", grown, 50)]))
+        );
+    }
+
+    #[test]
+    fn test_health_buckets() {
+        let keys = ZobristKeys::new(5, 5, 10);
+        let hash = |health| keys.hash(&board(5, 5, vec![snake_with_health("me", body(), health)]));
+        assert_eq!(hash(51), hash(59));
+        assert_ne!(hash(59), hash(60));
+    }
+}